@@ -0,0 +1,230 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+// languages written as executable files
+const SCRIPT_LANGUAGES: &[&str] = &[
+    "sh", "bash", "zsh", "shell", "python", "py", "python3", "perl", "ruby",
+];
+
+// language to file extension, anything else is .txt
+const EXTENSIONS: &[(&[&str], &str)] = &[
+    (&["sh", "bash", "zsh", "shell"], "sh"),
+    (&["python", "py", "python3"], "py"),
+    (&["rust", "rs"], "rs"),
+    (&["javascript", "js"], "js"),
+    (&["typescript", "ts"], "ts"),
+    (&["go", "golang"], "go"),
+    (&["json"], "json"),
+    (&["yaml", "yml"], "yaml"),
+    (&["toml"], "toml"),
+    (&["sql"], "sql"),
+    (&["html"], "html"),
+    (&["css"], "css"),
+    (&["markdown", "md"], "md"),
+    (&["perl"], "pl"),
+    (&["ruby", "rb"], "rb"),
+];
+
+// language to the interpreter used by /run-block
+const INTERPRETERS: &[(&[&str], &str)] = &[
+    (&["sh", "shell"], "/bin/sh"),
+    (&["bash"], "/usr/bin/env bash"),
+    (&["zsh"], "/usr/bin/env zsh"),
+    (&["python", "py", "python3"], "/usr/bin/env python3"),
+];
+
+/// A fenced block of a response i.e ```sh path=scripts/new.sh
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// 1 based, in order of appearance
+    pub index: usize,
+    pub language: Option<String>,
+    /// from a 'path=' hint on the opening fence
+    pub path: Option<String>,
+    pub content: String,
+}
+
+impl CodeBlock {
+    /// shell, python and other scripts, or anything starting with a shebang
+    pub fn is_script(&self) -> bool {
+        self.content.starts_with("#!")
+            || self
+                .language
+                .as_deref()
+                .is_some_and(|l| SCRIPT_LANGUAGES.contains(&l))
+    }
+
+    /// shell and python blocks can be run, i.e '/usr/bin/env python3'
+    pub fn interpreter(&self) -> Option<&'static str> {
+        let language = self.language.as_deref()?;
+        INTERPRETERS
+            .iter()
+            .find(|(names, _)| names.contains(&language))
+            .map(|(_, interpreter)| *interpreter)
+    }
+
+    /// the content with a shebang line for the interpreter when it has none
+    pub fn script(&self) -> Option<String> {
+        let interpreter = self.interpreter()?;
+        if self.content.starts_with("#!") {
+            return Some(self.content.clone());
+        }
+        Some(format!("#!{}\n{}", interpreter, self.content))
+    }
+
+    /// i.e '2-new.sh' from the path hint, '2.py' from the language
+    pub fn file_name(&self) -> String {
+        if let Some(name) = self.path.as_deref().and_then(|p| Path::new(p).file_name()) {
+            return format!("{}-{}", self.index, name.to_string_lossy());
+        }
+        let language = self.language.as_deref().unwrap_or_default();
+        let extension = EXTENSIONS
+            .iter()
+            .find(|(names, _)| names.contains(&language))
+            .map(|(_, ext)| *ext)
+            .unwrap_or("txt");
+        format!("{}.{}", self.index, extension)
+    }
+
+    /// write the block, scripts are made executable
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &self.content)?;
+        let mode = if self.is_script() { 0o755 } else { 0o644 };
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        Ok(())
+    }
+}
+
+/// fenced blocks opened with ``` or ~~~, the first word of the info string
+/// is the language and a 'path=' word the target file. Unclosed blocks are
+/// ignored
+pub fn parse_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = vec![];
+    // opening fence and the block being read
+    let mut open: Option<(String, CodeBlock)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let Some((opening, block)) = open.as_mut() else {
+            let Some(fence) = fence(trimmed) else {
+                continue;
+            };
+            let mut block = CodeBlock {
+                index: blocks.len() + 1,
+                language: None,
+                path: None,
+                content: String::new(),
+            };
+            for word in trimmed[fence.len()..].split_whitespace() {
+                match word.split_once('=') {
+                    Some(("path", value)) => {
+                        block.path = Some(value.trim_matches(['"', '\'']).to_string())
+                    }
+                    Some(_) => {}
+                    None if block.language.is_none() => block.language = Some(word.to_lowercase()),
+                    None => {}
+                }
+            }
+            open = Some((fence, block));
+            continue;
+        };
+        let closes =
+            fence(trimmed).is_some_and(|f| f.starts_with(opening.as_str()) && trimmed.trim() == f);
+        if closes {
+            blocks.extend(open.take().map(|(_, block)| block));
+        } else {
+            block.content.push_str(line);
+            block.content.push('\n');
+        }
+    }
+    blocks
+}
+
+// the run of 3 or more backticks or tildes a line starts with
+fn fence(line: &str) -> Option<String> {
+    let first = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence: String = line.chars().take_while(|c| *c == first).collect();
+    (fence.len() >= 3).then_some(fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_with_language_and_path() {
+        let text = "Run this:\n```sh path=\"scripts/new.sh\"\necho hi\n```\nand\n~~~Python\nprint(1)\n~~~\n";
+        let blocks = parse_blocks(text);
+        assert_eq!(
+            blocks,
+            vec![
+                CodeBlock {
+                    index: 1,
+                    language: Some("sh".to_string()),
+                    path: Some("scripts/new.sh".to_string()),
+                    content: "echo hi\n".to_string(),
+                },
+                CodeBlock {
+                    index: 2,
+                    language: Some("python".to_string()),
+                    path: None,
+                    content: "print(1)\n".to_string(),
+                },
+            ]
+        );
+        assert_eq!(blocks[0].file_name(), "1-new.sh");
+        assert_eq!(blocks[1].file_name(), "2.py");
+    }
+
+    #[test]
+    fn longer_fences_hold_shorter_ones() {
+        let text = "````md\n```sh\nls\n```\n````\n```\nplain\n```";
+        let blocks = parse_blocks(text);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].content, "```sh\nls\n```\n");
+        assert_eq!(blocks[1].language, None);
+        assert_eq!(blocks[1].file_name(), "2.txt");
+        // a fence with an info string does not close, nor does the other kind
+        let blocks = parse_blocks("```\na\n```sh\n~~~\nb\n```\n");
+        assert_eq!(blocks[0].content, "a\n```sh\n~~~\nb\n");
+    }
+
+    #[test]
+    fn unclosed_blocks_are_ignored() {
+        let blocks = parse_blocks("```sh\necho 1\n```\n```py\nprint(2)\n");
+        assert_eq!(blocks.len(), 1);
+        assert!(parse_blocks("no code here\n``not a fence``\n").is_empty());
+    }
+
+    #[test]
+    fn indented_blocks_keep_their_content() {
+        let blocks = parse_blocks("1. step\n   ```bash\n   make\n   ```\n");
+        assert_eq!(blocks[0].language.as_deref(), Some("bash"));
+        assert_eq!(blocks[0].content, "   make\n");
+    }
+
+    #[test]
+    fn scripts_get_a_shebang() {
+        let block = |language: &str, content: &str| CodeBlock {
+            index: 1,
+            language: Some(language.to_string()),
+            path: None,
+            content: content.to_string(),
+        };
+        assert_eq!(
+            block("python", "print(1)\n").script().as_deref(),
+            Some("#!/usr/bin/env python3\nprint(1)\n")
+        );
+        assert_eq!(
+            block("bash", "#!/bin/bash -e\nls\n").script().as_deref(),
+            Some("#!/bin/bash -e\nls\n")
+        );
+        assert_eq!(block("rust", "fn main() {}\n").script(), None);
+        assert!(block("ruby", "puts 1\n").is_script());
+        assert_eq!(block("ruby", "puts 1\n").interpreter(), None);
+        assert!(block("txt", "#!/bin/sh\n").is_script());
+    }
+}
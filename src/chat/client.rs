@@ -1,7 +1,11 @@
-use crate::chat::model::{ChatResponse, CompletionRequest};
+use crate::chat::model::{
+    ChatResponse, CompletionRequest, EmbeddingRequest, EmbeddingResponse, Message, Usage,
+};
+use crate::service::interrupt::interrupted;
 use async_trait::async_trait;
 use custom_logger as log;
 use http::StatusCode;
+use std::sync::Mutex;
 use std::time::Duration;
 
 #[async_trait]
@@ -10,39 +14,55 @@ pub trait ChatClient: Send + Sync {
         &self,
         request: CompletionRequest,
     ) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// the full response message, including any tool calls, nothing is printed
+    async fn complete_message(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<Message, Box<dyn std::error::Error>>;
+
+    async fn embed(
+        &self,
+        request: EmbeddingRequest,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>>;
+
+    /// token usage reported for the last completion
+    fn last_usage(&self) -> Option<Usage>;
 }
 
 pub struct OpenAIClient {
     api_key: String,
     base_url: String,
+    /// None when it cannot be derived from the api url and is not set
+    embeddings_url: Option<String>,
+    usage: Mutex<Option<Usage>>,
 }
 
 impl OpenAIClient {
     pub fn new(api_key: String, url: String) -> Self {
+        // openai schema services expose /v1/embeddings next to /v1/chat/completions
+        let embeddings_url = url
+            .strip_suffix("/chat/completions")
+            .map(|base| format!("{}/embeddings", base));
         Self {
             api_key,
             base_url: url,
+            embeddings_url,
+            usage: Mutex::new(None),
         }
     }
-}
 
-#[allow(unused_macros)]
-macro_rules! print_flush {
-    ( $($t:tt)* ) => {
-        {
-            let mut h = stdout();
-            write!(h, $($t)* ).unwrap();
-            h.flush().unwrap();
+    pub fn with_embeddings_url(mut self, url: Option<String>) -> Self {
+        if url.is_some() {
+            self.embeddings_url = url;
         }
+        self
     }
-}
 
-#[async_trait]
-impl ChatClient for OpenAIClient {
-    async fn complete(
+    async fn send_completion(
         &self,
         request: CompletionRequest,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<Message, Box<dyn std::error::Error>> {
         let json = serde_json::to_string(&request).unwrap();
         log::debug!("url {}", self.base_url);
         let client_res = reqwest::Client::builder()
@@ -83,7 +103,12 @@ impl ChatClient for OpenAIClient {
                             String::from_utf8(contents.to_vec()).unwrap()
                         );
                         let chat_response: ChatResponse = serde_json::from_slice(&contents)?;
-                        chat_response.choices[0].message.content.clone()
+                        *self.usage.lock().unwrap() = Some(chat_response.usage.clone());
+                        chat_response
+                            .choices
+                            .first()
+                            .map(|c| c.message.clone())
+                            .ok_or("[complete] llm openapi response has no choices".to_string())?
                     }
                     _ => {
                         let contents = result.bytes().await?;
@@ -104,8 +129,128 @@ impl ChatClient for OpenAIClient {
         //log::debug!("[complete] response {:?}", response.status());
         //let content: ChatResponse = serde_json::from_slice(&response.bytes().await?)?;
         //let result = content.choices[0].message.content.clone();
+        Ok(result)
+    }
+
+    async fn send_embedding(
+        &self,
+        request: EmbeddingRequest,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let url = self.embeddings_url.as_ref().ok_or(
+            "[embed] no embeddings url, set index.embeddings_url (api_url does not end in /chat/completions)"
+                .to_string(),
+        )?;
+        let json = serde_json::to_string(&request)?;
+        log::debug!("[embed] url {}", url);
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .http1_title_case_headers()
+            .timeout(Duration::new(1200, 0))
+            .build()
+            .map_err(|e| format!("[embed] llm openapi {} ", e))?;
+
+        let result = client
+            .post(url)
+            .bearer_auth(self.api_key.trim())
+            .header("Content-Type", "application/json")
+            .body(json)
+            .send()
+            .await
+            .map_err(|e| format!("[embed] llm openapi error {}", e))?;
+
+        let status = result.status();
+        log::debug!("[embed] llm openapi response status {}", status);
+        let contents = result.bytes().await?;
+        if status != StatusCode::OK {
+            return Err(Box::from(format!(
+                "[embed] llm openapi {}",
+                String::from_utf8(contents.to_vec()).unwrap_or("could not parse error".to_string())
+            )));
+        }
+        let mut response: EmbeddingResponse = serde_json::from_slice(&contents)?;
+        // the api does not guarantee ordering
+        response.data.sort_by_key(|d| d.index);
+        Ok(response.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+// ctrl-c stops the request and returns to the prompt, see interrupt
+async fn interruptible<T>(
+    name: &str,
+    request: impl Future<Output = Result<T, Box<dyn std::error::Error>>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    tokio::select! {
+        res = request => res,
+        _ = interrupted() => Err(Box::from(format!("[{}] interrupted", name))),
+    }
+}
+
+#[allow(unused_macros)]
+macro_rules! print_flush {
+    ( $($t:tt)* ) => {
+        {
+            let mut h = stdout();
+            write!(h, $($t)* ).unwrap();
+            h.flush().unwrap();
+        }
+    }
+}
+
+#[async_trait]
+impl ChatClient for OpenAIClient {
+    async fn complete(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let result = self
+            .complete_message(request)
+            .await?
+            .content
+            .unwrap_or_default();
         // preserve origin content (ie no log decorations)
         println!("{}", result);
         Ok(result)
     }
+
+    async fn complete_message(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<Message, Box<dyn std::error::Error>> {
+        interruptible("complete", self.send_completion(request)).await
+    }
+
+    async fn embed(
+        &self,
+        request: EmbeddingRequest,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        interruptible("embed", self.send_embedding(request)).await
+    }
+
+    fn last_usage(&self) -> Option<Usage> {
+        self.usage.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embeddings_url_is_derived_or_unset() {
+        let client = OpenAIClient::new(
+            "key".to_string(),
+            "https://api.example.com/v1/chat/completions".to_string(),
+        );
+        assert_eq!(
+            client.embeddings_url.as_deref(),
+            Some("https://api.example.com/v1/embeddings")
+        );
+        let client = OpenAIClient::new("key".to_string(), "http://localhost:8080/".to_string());
+        assert_eq!(client.embeddings_url, None);
+        let client = client.with_embeddings_url(Some("http://localhost:8081/embed".to_string()));
+        assert_eq!(
+            client.embeddings_url.as_deref(),
+            Some("http://localhost:8081/embed")
+        );
+    }
 }
@@ -0,0 +1,246 @@
+use crate::cli::schema::Spec;
+use crate::prompt::command::COMMANDS;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{
+    Cmd, CompletionType, Config, Context, Editor, EventHandler, Helper, KeyCode, KeyEvent,
+    Modifiers,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+use walkdir::WalkDir;
+
+const MAX_HISTORY: usize = 1000;
+// opens and closes a multi-line prompt
+const DELIMITER: &str = "\"\"\"";
+
+/// rustyline helper, completes slash commands, script names for /execute
+/// and file paths inside the configured folders
+pub struct ChatHelper {
+    working_dir: PathBuf,
+    folders: Vec<String>,
+}
+
+impl ChatHelper {
+    pub fn new(spec: &Spec) -> Self {
+        Self {
+            working_dir: PathBuf::from(&spec.working_dir),
+            folders: spec.folder_names(),
+        }
+    }
+
+    // partial is relative to working_dir, prefix is prepended to every
+    // replacement (i.e '@' for inline references)
+    fn complete_path(&self, partial: &str, prefix: &str) -> Vec<Pair> {
+        let Some((dir, name)) = partial.rsplit_once('/') else {
+            return self
+                .folders
+                .iter()
+                .filter(|f| f.starts_with(partial))
+                .map(|f| Pair {
+                    display: format!("{}/", f),
+                    replacement: format!("{}{}/", prefix, f),
+                })
+                .collect();
+        };
+        let folder = dir.split('/').next().unwrap_or_default();
+        if !self.folders.iter().any(|f| f == folder) || dir.split('/').any(|c| c == "..") {
+            return vec![];
+        }
+        let Ok(entries) = fs::read_dir(self.working_dir.join(dir)) else {
+            return vec![];
+        };
+        let mut pairs: Vec<Pair> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let file_name = e.file_name().to_string_lossy().to_string();
+                if file_name.starts_with('.') || !file_name.starts_with(name) {
+                    return None;
+                }
+                let suffix = if e.path().is_dir() { "/" } else { "" };
+                Some(Pair {
+                    display: format!("{}{}", file_name, suffix),
+                    replacement: format!("{}{}/{}{}", prefix, dir, file_name, suffix),
+                })
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.display.cmp(&b.display));
+        pairs
+    }
+
+    fn complete_command(&self, partial: &str) -> Vec<Pair> {
+        COMMANDS
+            .iter()
+            .filter_map(|(usage, _)| usage.split(' ').next())
+            .filter(|name| name.starts_with(partial))
+            .map(|name| Pair {
+                display: name.to_string(),
+                replacement: format!("{} ", name),
+            })
+            .collect()
+    }
+
+    // '/execute <dir> <name>', the name is completed from the files under dir
+    fn complete_script(&self, dir: &str, partial: &str) -> Vec<Pair> {
+        if !self.folders.iter().any(|f| f == dir) {
+            return vec![];
+        }
+        let root = self.working_dir.join(dir);
+        let mut pairs: Vec<Pair> = WalkDir::new(&root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| {
+                let relative = e
+                    .path()
+                    .strip_prefix(&root)
+                    .ok()?
+                    .to_string_lossy()
+                    .to_string();
+                if relative.starts_with('.') || !relative.starts_with(partial) {
+                    return None;
+                }
+                Some(Pair {
+                    display: relative.clone(),
+                    replacement: relative,
+                })
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.display.cmp(&b.display));
+        pairs
+    }
+}
+
+impl Completer for ChatHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+        if let Some(partial) = word.strip_prefix('@') {
+            return Ok((start, self.complete_path(partial, "@")));
+        }
+        let pairs = match previous.as_slice() {
+            [] if word.starts_with('/') => self.complete_command(word),
+            ["/execute"] if word.contains('/') => self.complete_path(word, ""),
+            ["/execute"] => self
+                .complete_path(word, "")
+                .into_iter()
+                .filter(|p| p.replacement.ends_with('/'))
+                .map(|p| Pair {
+                    replacement: format!("{} ", p.replacement.trim_end_matches('/')),
+                    display: p.display,
+                })
+                .collect(),
+            ["/execute", dir] => self.complete_script(dir, word),
+            ["/read"] | ["/summarize"] => self.complete_path(word, ""),
+            _ => vec![],
+        };
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ChatHelper {}
+
+// enter inside an open """ block inserts a newline instead of submitting
+impl Validator for ChatHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input().trim_start();
+        if input.starts_with(DELIMITER) && input.matches(DELIMITER).count() < 2 {
+            return Ok(ValidationResult::Incomplete);
+        }
+        Ok(ValidationResult::Valid(None))
+    }
+}
+
+impl Helper for ChatHelper {}
+
+pub fn history_path(spec: &Spec) -> PathBuf {
+    Path::new(&spec.working_dir).join(".history")
+}
+
+/// line editor with emacs bindings (ctrl-r reverse search) and history
+/// loaded from working_dir/.history
+pub fn new_editor(spec: &Spec) -> rustyline::Result<Editor<ChatHelper, DefaultHistory>> {
+    let config = Config::builder()
+        .max_history_size(MAX_HISTORY)?
+        .history_ignore_dups(true)?
+        .history_ignore_space(true)
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = Editor::with_config(config)?;
+    editor.set_helper(Some(ChatHelper::new(spec)));
+    // alt-enter adds a line without submitting
+    editor.bind_sequence(
+        KeyEvent(KeyCode::Enter, Modifiers::ALT),
+        EventHandler::Simple(Cmd::Newline),
+    );
+    let path = history_path(spec);
+    if path.exists() {
+        editor.load_history(&path)?;
+    }
+    Ok(editor)
+}
+
+/// remove the surrounding """ of a multi-line prompt
+pub fn strip_delimiters(input: &str) -> String {
+    let trimmed = input.trim();
+    match trimmed.strip_prefix(DELIMITER) {
+        Some(inner) => inner
+            .strip_suffix(DELIMITER)
+            .unwrap_or(inner)
+            .trim()
+            .to_string(),
+        None => trimmed.to_string(),
+    }
+}
+
+/// open $EDITOR (vi when unset) on a temp file pre-filled with text, returns
+/// the saved contents or None when the file was left empty
+pub fn edit_in_editor(text: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or("vi".to_string());
+    // i.e EDITOR="code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let path = std::env::temp_dir().join(format!("aichat-prompt-{}.md", std::process::id()));
+    fs::write(&path, text)?;
+    let status = ProcessCommand::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("[edit_in_editor] failed to start '{}' : {}", editor, e));
+    let contents = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    let status = status?;
+    if !status.success() {
+        return Err(Box::from(format!(
+            "[edit_in_editor] '{}' exited with {}",
+            editor, status
+        )));
+    }
+    let contents = contents?.trim().to_string();
+    if contents.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(contents))
+}
@@ -0,0 +1,274 @@
+use crate::chat::model::Usage;
+use crate::cli::schema::Spec;
+use chrono::{Local, SecondsFormat};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+/// Front-matter of a saved response
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InferenceMeta {
+    #[serde(rename = "created")]
+    pub created: String,
+    /// prompt, ask, fix, structured, summarize, summarize-map,
+    /// summarize-reduce or workflow
+    #[serde(rename = "kind")]
+    pub kind: String,
+    #[serde(rename = "model")]
+    pub model: String,
+    #[serde(rename = "temperature")]
+    pub temperature: f32,
+    #[serde(rename = "top_p")]
+    pub top_p: f32,
+    #[serde(rename = "max_tokens")]
+    pub max_tokens: usize,
+    #[serde(rename = "prompt_sha256")]
+    pub prompt_sha256: String,
+    #[serde(rename = "prompt_tokens", default)]
+    pub prompt_tokens: Option<i64>,
+    #[serde(rename = "completion_tokens", default)]
+    pub completion_tokens: Option<i64>,
+    #[serde(rename = "total_tokens", default)]
+    pub total_tokens: Option<i64>,
+}
+
+impl InferenceMeta {
+    pub fn new(spec: &Spec, kind: &str, prompt: &str, usage: Option<Usage>) -> Self {
+        Self {
+            created: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+            kind: kind.to_string(),
+            model: spec.model.clone(),
+            temperature: spec.temperature,
+            top_p: spec.top_p,
+            max_tokens: spec.max_tokens,
+            prompt_sha256: format!("{:x}", Sha256::digest(prompt.as_bytes())),
+            prompt_tokens: usage.as_ref().map(|u| u.prompt_tokens),
+            completion_tokens: usage.as_ref().map(|u| u.completion_tokens),
+            total_tokens: usage.as_ref().map(|u| u.total_tokens),
+        }
+    }
+}
+
+/// A response read back from staging/history
+pub struct HistoryEntry {
+    /// file name i.e 20261019-143005-123.md
+    pub name: String,
+    pub meta: Option<InferenceMeta>,
+    pub body: String,
+}
+
+/// Every response as a timestamped file in staging/history, the newest is
+/// linked from staging/latest.md
+pub struct History {
+    staging: PathBuf,
+}
+
+impl History {
+    pub fn new(spec: &Spec) -> Self {
+        Self {
+            staging: Path::new(&spec.working_dir).join("staging"),
+        }
+    }
+
+    fn folder(&self) -> PathBuf {
+        self.staging.join("history")
+    }
+
+    /// write the response with its front-matter and point latest.md at it
+    pub fn save(
+        &self,
+        meta: &InferenceMeta,
+        content: &str,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.folder())?;
+        let stamp = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+        let mut name = format!("{}.md", stamp);
+        let mut n = 1;
+        while self.folder().join(&name).exists() {
+            n += 1;
+            name = format!("{}-{}.md", stamp, n);
+        }
+        let path = self.folder().join(&name);
+        fs::write(
+            &path,
+            format!(
+                "{}\n{}{}\n{}",
+                FRONT_MATTER_DELIMITER,
+                serde_yaml::to_string(meta)?,
+                FRONT_MATTER_DELIMITER,
+                content
+            ),
+        )?;
+
+        let latest = self.staging.join("latest.md");
+        if latest.symlink_metadata().is_ok() {
+            fs::remove_file(&latest)?;
+        }
+        // a copy where symlinks are not possible
+        if std::os::unix::fs::symlink(Path::new("history").join(&name), &latest).is_err() {
+            fs::copy(&path, &latest)?;
+        }
+        Ok(path)
+    }
+
+    /// saved responses, oldest first
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        let Ok(dir) = fs::read_dir(self.folder()) else {
+            return Ok(vec![]);
+        };
+        let mut names: Vec<String> = dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with(".md"))
+            .collect();
+        names.sort();
+        let mut entries = vec![];
+        for name in names {
+            let text = fs::read_to_string(self.folder().join(&name))?;
+            let (meta, body) = split_front_matter(&text);
+            entries.push(HistoryEntry {
+                name,
+                meta,
+                body: body.to_string(),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// unified diff of two responses, each given as its number in
+    /// '/history files' (negative counts back from the newest), its file
+    /// name or 'latest'
+    pub fn diff(&self, from: &str, to: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let entries = self.entries()?;
+        let from = find(&entries, from)?;
+        let to = find(&entries, to)?;
+        Ok(TextDiff::from_lines(&from.body, &to.body)
+            .unified_diff()
+            .context_radius(3)
+            .header(&from.name, &to.name)
+            .to_string())
+    }
+}
+
+fn find<'a>(entries: &'a [HistoryEntry], id: &str) -> Result<&'a HistoryEntry, String> {
+    let found = match id {
+        "latest" => entries.last(),
+        _ => match id.parse::<i64>() {
+            Ok(n) if n > 0 => entries.get(n as usize - 1),
+            Ok(n) => entries
+                .len()
+                .checked_sub(n.unsigned_abs() as usize)
+                .and_then(|i| entries.get(i)),
+            Err(_) => entries
+                .iter()
+                .find(|e| e.name == id || e.name.trim_end_matches(".md") == id),
+        },
+    };
+    found.ok_or(format!(
+        "[history] no response '{}' ({} saved, see /history files)",
+        id,
+        entries.len()
+    ))
+}
+
+// front-matter between the leading '---' lines, files without one are all body
+fn split_front_matter(text: &str) -> (Option<InferenceMeta>, &str) {
+    let Some(rest) = text.strip_prefix(&format!("{}\n", FRONT_MATTER_DELIMITER)) else {
+        return (None, text);
+    };
+    let Some(end) = rest.find(&format!("\n{}\n", FRONT_MATTER_DELIMITER)) else {
+        return (None, text);
+    };
+    let meta = serde_yaml::from_str(&rest[..end]).ok();
+    (meta, &rest[end + FRONT_MATTER_DELIMITER.len() + 2..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<HistoryEntry> {
+        names
+            .iter()
+            .map(|name| HistoryEntry {
+                name: name.to_string(),
+                meta: None,
+                body: format!("body of {}", name),
+            })
+            .collect()
+    }
+
+    fn found(entries: &[HistoryEntry], id: &str) -> Result<String, String> {
+        find(entries, id).map(|e| e.name.clone())
+    }
+
+    #[test]
+    fn find_by_number_name_or_latest() {
+        let saved = entries(&[
+            "20261019-100000-000.md",
+            "20261019-110000-000.md",
+            "20261019-120000-000.md",
+        ]);
+        assert_eq!(found(&saved, "1"), Ok("20261019-100000-000.md".to_string()));
+        assert_eq!(found(&saved, "3"), Ok("20261019-120000-000.md".to_string()));
+        assert_eq!(
+            found(&saved, "latest"),
+            Ok("20261019-120000-000.md".to_string())
+        );
+        // negative counts back from the newest, -1 is the newest
+        assert_eq!(
+            found(&saved, "-1"),
+            Ok("20261019-120000-000.md".to_string())
+        );
+        assert_eq!(
+            found(&saved, "-2"),
+            Ok("20261019-110000-000.md".to_string())
+        );
+        assert_eq!(
+            found(&saved, "20261019-110000-000"),
+            Ok("20261019-110000-000.md".to_string())
+        );
+        assert_eq!(
+            found(&saved, "20261019-110000-000.md"),
+            Ok("20261019-110000-000.md".to_string())
+        );
+    }
+
+    #[test]
+    fn find_out_of_range() {
+        let saved = entries(&["a.md", "b.md"]);
+        for id in ["0", "3", "-3", "c", ""] {
+            assert!(found(&saved, id).is_err(), "{:?}", id);
+        }
+        assert!(found(&[], "latest").is_err());
+        assert!(found(&[], "-2").is_err());
+    }
+
+    #[test]
+    fn front_matter_is_split_from_the_body() {
+        let meta = InferenceMeta {
+            kind: "prompt".to_string(),
+            model: "gpt-oss-120b".to_string(),
+            total_tokens: Some(42),
+            ..InferenceMeta::default()
+        };
+        let text = format!(
+            "---\n{}---\nanswer\n---\nmore\n",
+            serde_yaml::to_string(&meta).unwrap()
+        );
+        let (parsed, body) = split_front_matter(&text);
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.kind, "prompt");
+        assert_eq!(parsed.total_tokens, Some(42));
+        assert_eq!(body, "answer\n---\nmore\n");
+        assert!(split_front_matter("plain answer").0.is_none());
+        assert_eq!(
+            split_front_matter("---\nunterminated").1,
+            "---\nunterminated"
+        );
+    }
+}
@@ -1,3 +1,7 @@
+pub mod blocks;
 pub mod client;
+pub mod editor;
+pub mod history;
 pub mod model;
 pub mod process;
+pub mod structured;
@@ -1,9 +1,16 @@
+use crate::cli::schema::Spec;
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct InputMessage {
     pub role: String,
     pub content: String,
+    /// set on assistant messages that requested tool calls
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// set on tool result messages
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tool_call_id: Option<String>,
 }
 
 #[allow(unused)]
@@ -12,6 +19,8 @@ impl InputMessage {
         Self {
             role: "system".to_string(),
             content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -19,6 +28,8 @@ impl InputMessage {
         Self {
             role: "user".to_string(),
             content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -27,11 +38,29 @@ impl InputMessage {
         Self {
             role: "assistant".to_string(),
             content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    pub fn tool_calls(content: impl ToString, calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls: Some(calls),
+            ..Self::assistant(content)
+        }
+    }
+
+    pub fn tool_result(call_id: &str, content: impl ToString) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.to_string(),
+            tool_calls: None,
+            tool_call_id: Some(call_id.to_string()),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CompletionRequest {
     pub model: String,
     pub messages: Vec<InputMessage>,
@@ -45,6 +74,90 @@ pub struct CompletionRequest {
     pub temperature: Option<f32>,
     pub stream: bool,
     pub max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ToolDefinition>>,
+}
+
+impl CompletionRequest {
+    pub fn new(spec: &Spec, messages: Vec<InputMessage>) -> Self {
+        Self {
+            model: spec.model.clone(),
+            messages,
+            top_p: spec.top_p,
+            temperature: Some(spec.temperature),
+            stream: spec.stream,
+            max_tokens: spec.max_tokens,
+            response_format: None,
+            tools: None,
+        }
+    }
+}
+
+/// openai response_format, either {"type":"json_object"} or
+/// {"type":"json_schema","json_schema":{...}}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    pub schema: serde_json::Value,
+    pub strict: bool,
+}
+
+/// openai function tool {"type":"function","function":{...}}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// json encoded arguments
+    pub arguments: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    pub embedding: Vec<f32>,
+    pub index: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -73,8 +186,12 @@ pub struct Choice {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Message {
-    pub content: String,
+    /// null when the model only requested tool calls
+    #[serde(default)]
+    pub content: Option<String>,
     pub role: String,
+    #[serde(rename = "tool_calls", default)]
+    pub tool_calls: Option<Vec<ToolCall>>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -1,10 +1,15 @@
 use crate::chat::client::OpenAIClient;
+use crate::chat::editor::{edit_in_editor, history_path, new_editor, strip_delimiters};
 use crate::cli::schema::ApplicationConfig;
+use crate::prompt::command::{Command, print_help};
+use crate::prompt::interact::ConsoleInteract;
 use crate::prompt::parser::PromptParser;
 use crate::service::execute::{Execute, ExecuteInterface};
+use crate::tools::manifest::ToolRegistry;
 use custom_logger as log;
+use rustyline::ExternalPrinter;
+use rustyline::error::ReadlineError;
 use std::fs;
-use std::io::{self, Write};
 use std::sync::Arc;
 
 #[allow(unused)]
@@ -20,10 +25,7 @@ impl ChatSession {
     pub async fn chat(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         log::info!("[chat] welcome!! input your question at the prompt");
         println!();
-        log::info!("menu :");
-        log::info!("     : type 'execute <service>'  to execute a service");
-        log::info!("     : type 'show current' to console print current session content");
-        log::info!("     : type 'exit' to quit");
+        print_help();
         println!();
 
         // Read and trim API key
@@ -32,27 +34,88 @@ impl ChatSession {
             .trim()
             .to_string();
 
-        let client = Arc::new(OpenAIClient::new(api_key, self.config.spec.api_url.clone()));
-        let mut ep = Execute::new(client, self.config.clone());
+        let client = Arc::new(
+            OpenAIClient::new(api_key, self.config.spec.api_url.clone())
+                .with_embeddings_url(self.config.spec.index.embeddings_url.clone()),
+        );
+        let mut ep = Execute::new(client, self.config.clone())?;
+        let interact = ConsoleInteract {};
 
-        loop {
-            print!("prompt> ");
-            io::stdout().flush()?;
+        let mut editor = new_editor(&self.config.spec)?;
+        let history = history_path(&self.config.spec);
+
+        // finished jobs are announced above the prompt while it is shown,
+        // without a terminal they are printed before the next prompt
+        let mut notices = Some(ep.jobs.subscribe());
+        if let Ok(mut printer) = editor.create_external_printer()
+            && let Some(mut receiver) = notices.take()
+        {
+            tokio::spawn(async move {
+                while let Some(notice) = receiver.recv().await {
+                    let _ = printer.print(format!("{}\n", notice));
+                }
+            });
+        }
+
+        // raw text of the last prompt sent to the model, pre-fills /edit
+        let mut last_prompt = String::new();
 
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            input = input.trim().to_string();
+        loop {
+            if let Some(receiver) = notices.as_mut() {
+                while let Ok(notice) = receiver.try_recv() {
+                    println!("{}", notice);
+                }
+            }
+            let line = match editor.readline("prompt> ") {
+                Ok(line) => line.trim().to_string(),
+                // ctrl-c clears the line, ctrl-d exits
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => {
+                    log::info!("[chat] exiting session");
+                    break;
+                }
+                Err(err) => return Err(Box::from(format!("[chat] {}", err))),
+            };
 
+            let mut input = strip_delimiters(&line);
             if input.is_empty() {
                 continue;
             }
+            let _ = editor.add_history_entry(line.as_str());
+            if let Err(err) = editor.save_history(&history) {
+                log::warn!("[chat] failed to save history : {}", err);
+            }
 
-            if input == "exit" {
+            let Some(mut parsed_command) = self.parse(&ep.tools, input.clone(), &interact).await
+            else {
+                continue;
+            };
+            if parsed_command == Command::Edit {
+                input = match edit_in_editor(&last_prompt) {
+                    Ok(Some(text)) => text,
+                    Ok(None) => {
+                        log::info!("[chat] edit cancelled, nothing to send");
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("[chat] {}", err);
+                        continue;
+                    }
+                };
+                println!("{}", input);
+                let Some(command) = self.parse(&ep.tools, input.clone(), &interact).await else {
+                    continue;
+                };
+                parsed_command = command;
+            }
+            if parsed_command == Command::Exit {
                 log::info!("[chat] exiting session");
                 break;
             }
+            if matches!(parsed_command, Command::Prompt(_)) {
+                last_prompt = input;
+            }
 
-            let parsed_command = PromptParser::parse(self.config.spec.working_dir.clone(), input)?;
             let res = ep.process_task(parsed_command).await;
             // we don't want to crash so lets handle the error
             match res {
@@ -63,6 +126,29 @@ impl ChatSession {
             }
             println!();
         }
+        let running = ep.jobs.running();
+        if running > 0 {
+            log::warn!("[chat] stopping {} running job(s)", running);
+            ep.jobs
+                .stop_all(self.config.spec.execute.kill_grace_secs)
+                .await;
+        }
         Ok(())
     }
+
+    // parse errors are logged, the session carries on
+    async fn parse(
+        &self,
+        tools: &ToolRegistry,
+        input: String,
+        interact: &ConsoleInteract,
+    ) -> Option<Command> {
+        match PromptParser::parse(&self.config.spec, tools, input, interact).await {
+            Ok(command) => Some(command),
+            Err(err) => {
+                log::error!("[chat] {}", err);
+                None
+            }
+        }
+    }
 }
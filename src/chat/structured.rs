@@ -0,0 +1,129 @@
+use crate::chat::model::{JsonSchemaFormat, ResponseFormat};
+use crate::cli::schema::ResponseFormatConfig;
+use custom_logger as log;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Local validation of json_object / json_schema responses
+pub struct StructuredOutput {
+    format: ResponseFormat,
+    validator: Option<jsonschema::Validator>,
+    pub max_retries: usize,
+}
+
+impl StructuredOutput {
+    pub fn new(
+        working_dir: &str,
+        cfg: &ResponseFormatConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match cfg.format_type.as_str() {
+            "json_object" => Ok(Self {
+                format: ResponseFormat {
+                    format_type: "json_object".to_string(),
+                    json_schema: None,
+                },
+                validator: None,
+                max_retries: cfg.max_retries,
+            }),
+            "json_schema" => {
+                let file = cfg.schema_file.clone().ok_or(
+                    "[structured] response_format json_schema requires a schema_file".to_string(),
+                )?;
+                let path = Path::new(working_dir).join(&file);
+                let data = fs::read_to_string(&path).map_err(|e| {
+                    format!(
+                        "[structured] failed to read schema file '{}': {}",
+                        path.display(),
+                        e
+                    )
+                })?;
+                let schema: Value = serde_json::from_str(&data)
+                    .map_err(|e| format!("[structured] invalid JSON in schema file: {}", e))?;
+                let validator = jsonschema::validator_for(&schema)
+                    .map_err(|e| format!("[structured] invalid schema '{}': {}", file, e))?;
+                let name = Path::new(&file)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or("response".to_string());
+                log::debug!("[structured] loaded schema {}", path.display());
+                Ok(Self {
+                    format: ResponseFormat {
+                        format_type: "json_schema".to_string(),
+                        json_schema: Some(JsonSchemaFormat {
+                            name,
+                            schema,
+                            strict: true,
+                        }),
+                    },
+                    validator: Some(validator),
+                    max_retries: cfg.max_retries,
+                })
+            }
+            other => Err(Box::from(format!(
+                "[structured] unsupported response_format type '{}' (use json_object or json_schema)",
+                other
+            ))),
+        }
+    }
+
+    pub fn response_format(&self) -> ResponseFormat {
+        self.format.clone()
+    }
+
+    /// parse and validate the model content, on failure returns a list of
+    /// human readable errors that can be fed back to the model
+    pub fn validate(&self, content: &str) -> Result<Value, Vec<String>> {
+        let value: Value = match serde_json::from_str(strip_fences(content)) {
+            Ok(value) => value,
+            Err(e) => return Err(vec![format!("response is not valid JSON: {}", e)]),
+        };
+        match &self.validator {
+            Some(validator) => {
+                let errors: Vec<String> = validator
+                    .iter_errors(&value)
+                    .map(|e| {
+                        let path = e.instance_path.to_string();
+                        if path.is_empty() {
+                            format!("{}", e)
+                        } else {
+                            format!("{} (at {})", e, path)
+                        }
+                    })
+                    .collect();
+                if errors.is_empty() {
+                    Ok(value)
+                } else {
+                    Err(errors)
+                }
+            }
+            None => {
+                if value.is_object() {
+                    Ok(value)
+                } else {
+                    Err(vec!["response must be a JSON object".to_string()])
+                }
+            }
+        }
+    }
+
+    /// message sent back to the model after a failed validation
+    pub fn retry_prompt(&self, errors: &[String]) -> String {
+        format!(
+            "Your previous response failed validation with the following errors:\n- {}\nRespond again with only the corrected JSON, no commentary.",
+            errors.join("\n- ")
+        )
+    }
+}
+
+// models often wrap json in a ```json fence even when asked not to
+fn strip_fences(content: &str) -> &str {
+    let trimmed = content.trim();
+    match trimmed.strip_prefix("```") {
+        Some(rest) => {
+            let body = rest.split_once('\n').map(|(_, b)| b).unwrap_or("");
+            body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+        }
+        None => trimmed,
+    }
+}
@@ -1,5 +1,7 @@
 use clap::Parser;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
 
 /// rust-container-tool cli struct
 #[derive(Parser, Debug)]
@@ -22,6 +24,14 @@ pub struct Cli {
     /// use speech-to-text service for prompting
     #[arg(long, value_name = "stt", default_value_t = false)]
     pub stt: bool,
+
+    /// run a workflow from working_dir/workflows and exit
+    #[arg(long, value_name = "workflow")]
+    pub workflow: Option<String>,
+
+    /// workflow variable as KEY=value, can be repeated
+    #[arg(long, value_name = "var")]
+    pub var: Vec<String>,
 }
 
 /// Application configuration
@@ -65,7 +75,586 @@ pub struct Spec {
     #[serde(rename = "working_dir")]
     pub working_dir: String,
     #[serde(rename = "folders")]
-    pub folders: Vec<String>,
+    pub folders: Vec<FolderConfig>,
     #[serde(rename = "system_prompt")]
     pub system_prompt: String,
+    #[serde(rename = "response_format", default)]
+    pub response_format: Option<ResponseFormatConfig>,
+    #[serde(rename = "index", default)]
+    pub index: IndexConfig,
+    #[serde(rename = "summarize", default)]
+    pub summarize: SummarizeConfig,
+    #[serde(rename = "attach", default)]
+    pub attach: AttachConfig,
+    #[serde(rename = "extract", default)]
+    pub extract: ExtractConfig,
+    /// per script settings keyed by path relative to working_dir
+    /// i.e "scripts/wrig-jira.sh"
+    #[serde(rename = "scripts", default)]
+    pub scripts: HashMap<String, ScriptConfig>,
+    #[serde(rename = "execute", default)]
+    pub execute: ExecuteConfig,
+    #[serde(rename = "tools", default)]
+    pub tools: ToolsConfig,
+    #[serde(rename = "approval", default)]
+    pub approval: ApprovalConfig,
+    #[serde(rename = "staging", default)]
+    pub staging: StagingConfig,
+    #[serde(rename = "blocks", default)]
+    pub blocks: BlocksConfig,
+}
+
+/// Fenced code blocks found in responses
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlocksConfig {
+    /// write the blocks of every response to staging/blocks
+    #[serde(rename = "auto_extract", default)]
+    pub auto_extract: bool,
+    /// corrected blocks asked from the model by '/run-block <n> fix'
+    #[serde(rename = "max_fix_rounds", default = "default_max_fix_rounds")]
+    pub max_fix_rounds: usize,
+}
+
+impl Default for BlocksConfig {
+    fn default() -> Self {
+        Self {
+            auto_extract: false,
+            max_fix_rounds: default_max_fix_rounds(),
+        }
+    }
+}
+
+fn default_max_fix_rounds() -> usize {
+    3
+}
+
+/// What happens to working_dir/staging at startup
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StagingConfig {
+    #[serde(rename = "on_start", default)]
+    pub on_start: StartupPolicy,
+    /// files older than this are removed from each staging folder
+    #[serde(rename = "max_age_days", default)]
+    pub max_age_days: Option<u64>,
+    /// newest files kept in each staging folder
+    #[serde(rename = "max_files", default)]
+    pub max_files: Option<usize>,
+    /// newest archives kept in staging/archive, 0 keeps them all
+    #[serde(rename = "max_archives", default = "default_max_archives")]
+    pub max_archives: usize,
+}
+
+impl Default for StagingConfig {
+    fn default() -> Self {
+        Self {
+            on_start: StartupPolicy::default(),
+            max_age_days: None,
+            max_files: None,
+            max_archives: default_max_archives(),
+        }
+    }
+}
+
+fn default_max_archives() -> usize {
+    10
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum StartupPolicy {
+    /// previous outputs stay in place
+    #[serde(rename = "keep")]
+    Keep,
+    /// previous outputs are moved to staging/archive/<timestamp>.tar
+    #[default]
+    #[serde(rename = "archive")]
+    Archive,
+    /// previous outputs are deleted, archives are kept
+    #[serde(rename = "purge")]
+    Purge,
+}
+
+/// Confirmation policy applied before any script runs
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ApprovalConfig {
+    #[serde(rename = "mode", default)]
+    pub mode: ApprovalMode,
+    /// tool names or script paths (relative to working_dir) that run
+    /// without asking in the allow-list and read-only modes
+    #[serde(rename = "allow", default)]
+    pub allow: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ApprovalMode {
+    /// every run is confirmed
+    #[default]
+    #[serde(rename = "ask")]
+    Ask,
+    #[serde(rename = "allow-list")]
+    AllowList,
+    /// tools declared read-only in tools.json and the allow list run
+    /// without asking
+    #[serde(rename = "read-only")]
+    ReadOnly,
+    /// nothing runs
+    #[serde(rename = "deny")]
+    Deny,
+}
+
+/// Tool manifest location and what is offered to the model
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolsConfig {
+    /// folder (relative to working_dir) holding tools.json and the scripts
+    #[serde(rename = "folder", default = "default_tools_folder")]
+    pub folder: String,
+    /// tools up to this side effect level are offered to the model, none
+    /// when not set
+    #[serde(rename = "expose", default)]
+    pub expose: Option<SideEffect>,
+    /// maximum rounds of tool calls for a single prompt
+    #[serde(rename = "max_rounds", default = "default_max_rounds")]
+    pub max_rounds: usize,
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            folder: default_tools_folder(),
+            expose: None,
+            max_rounds: default_max_rounds(),
+        }
+    }
+}
+
+fn default_tools_folder() -> String {
+    "scripts".to_string()
+}
+
+fn default_max_rounds() -> usize {
+    5
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub enum SideEffect {
+    #[serde(rename = "read-only")]
+    ReadOnly,
+    #[default]
+    #[serde(rename = "mutating")]
+    Mutating,
+    #[serde(rename = "dangerous")]
+    Dangerous,
+}
+
+impl std::fmt::Display for SideEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SideEffect::ReadOnly => write!(f, "read-only"),
+            SideEffect::Mutating => write!(f, "mutating"),
+            SideEffect::Dangerous => write!(f, "dangerous"),
+        }
+    }
+}
+
+/// Limits applied to every script execution
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecuteConfig {
+    /// default timeout in seconds, 0 disables it
+    #[serde(rename = "timeout_secs", default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// time between SIGTERM and SIGKILL when a script is stopped
+    #[serde(rename = "kill_grace_secs", default = "default_kill_grace_secs")]
+    pub kill_grace_secs: u64,
+    /// output kept per stream (stdout and stderr), the rest is dropped
+    #[serde(rename = "max_output_bytes", default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    #[serde(rename = "max_output_lines", default = "default_max_output_lines")]
+    pub max_output_lines: usize,
+    /// when the output of a run is added to the session context, it is
+    /// always added with '/execute ... and ask <question>'
+    #[serde(rename = "feedback", default)]
+    pub feedback: FeedbackMode,
+    /// characters of output kept in the session context
+    #[serde(rename = "feedback_chars", default = "default_feedback_chars")]
+    pub feedback_chars: usize,
+    #[serde(rename = "isolation", default)]
+    pub isolation: IsolationConfig,
+}
+
+/// Linux process isolation for scripts, off by default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IsolationConfig {
+    #[serde(rename = "enabled", default)]
+    pub enabled: bool,
+    /// when false the script runs in its own (empty) network namespace
+    #[serde(rename = "network", default)]
+    pub network: bool,
+    /// mount an empty tmpfs on /tmp for each run
+    #[serde(rename = "private_tmp", default = "default_true")]
+    pub private_tmp: bool,
+    /// restrict file access to the folders and the system paths below
+    #[serde(rename = "landlock", default = "default_true")]
+    pub landlock: bool,
+    #[serde(rename = "max_cpu_secs", default = "default_max_cpu_secs")]
+    pub max_cpu_secs: u64,
+    /// address space (RLIMIT_AS), runtimes like the jvm, go or node reserve
+    /// far more than they use, 0 is unlimited
+    #[serde(rename = "max_memory_mb", default)]
+    pub max_memory_mb: u64,
+    #[serde(rename = "max_file_mb", default = "default_max_file_mb")]
+    pub max_file_mb: u64,
+    /// RLIMIT_NPROC counts every process of the user, not only the
+    /// script's, 0 is unlimited
+    #[serde(rename = "max_processes", default)]
+    pub max_processes: u64,
+    /// variables kept from our environment, everything else is dropped
+    #[serde(rename = "env", default = "default_isolation_env")]
+    pub env: Vec<String>,
+    /// readable (and executable) paths outside the folders
+    #[serde(rename = "read_paths", default = "default_read_paths")]
+    pub read_paths: Vec<String>,
+    /// writable paths outside the folders
+    #[serde(rename = "write_paths", default = "default_write_paths")]
+    pub write_paths: Vec<String>,
+}
+
+impl Default for IsolationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            network: false,
+            private_tmp: default_true(),
+            landlock: default_true(),
+            max_cpu_secs: default_max_cpu_secs(),
+            max_memory_mb: 0,
+            max_file_mb: default_max_file_mb(),
+            max_processes: 0,
+            env: default_isolation_env(),
+            read_paths: default_read_paths(),
+            write_paths: default_write_paths(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_cpu_secs() -> u64 {
+    600
+}
+
+fn default_max_file_mb() -> u64 {
+    1024
+}
+
+fn default_isolation_env() -> Vec<String> {
+    ["PATH", "HOME", "USER", "LOGNAME", "LANG", "LC_ALL", "TERM"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_read_paths() -> Vec<String> {
+    [
+        "/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/opt", "/proc", "/sys",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_write_paths() -> Vec<String> {
+    vec!["/dev".to_string()]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum FeedbackMode {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "failure")]
+    Failure,
+    #[serde(rename = "always")]
+    Always,
+}
+
+impl Default for ExecuteConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            kill_grace_secs: default_kill_grace_secs(),
+            max_output_bytes: default_max_output_bytes(),
+            max_output_lines: default_max_output_lines(),
+            feedback: FeedbackMode::default(),
+            feedback_chars: default_feedback_chars(),
+            isolation: IsolationConfig::default(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    600
+}
+
+fn default_kill_grace_secs() -> u64 {
+    5
+}
+
+fn default_max_output_bytes() -> usize {
+    1_000_000
+}
+
+fn default_max_output_lines() -> usize {
+    10_000
+}
+
+fn default_feedback_chars() -> usize {
+    8000
+}
+
+/// Settings applied when a script is executed
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ScriptConfig {
+    /// environment variables set for the script, named parameters given on
+    /// the command line take precedence
+    #[serde(rename = "env", default)]
+    pub env: HashMap<String, String>,
+    /// exit codes mapped to OK and WARNING, any other code is an ERROR
+    #[serde(rename = "exit_codes", default)]
+    pub exit_codes: ExitCodes,
+    /// honour the legacy 'exit => 0' / 'exit => 1' stdout markers
+    #[serde(rename = "legacy_markers", default)]
+    pub legacy_markers: bool,
+    /// overrides execute.timeout_secs
+    #[serde(rename = "timeout_secs", default)]
+    pub timeout_secs: Option<u64>,
+    /// overrides execute.isolation.network
+    #[serde(rename = "network", default)]
+    pub network: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExitCodes {
+    #[serde(rename = "ok", default = "default_ok_codes")]
+    pub ok: Vec<i32>,
+    #[serde(rename = "warning", default)]
+    pub warning: Vec<i32>,
+}
+
+impl Default for ExitCodes {
+    fn default() -> Self {
+        Self {
+            ok: default_ok_codes(),
+            warning: vec![],
+        }
+    }
+}
+
+fn default_ok_codes() -> Vec<i32> {
+    vec![0]
+}
+
+/// Text extraction for pdf, docx, html and csv files
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtractConfig {
+    /// number of csv rows included after the schema summary
+    #[serde(rename = "csv_rows", default = "default_csv_rows")]
+    pub csv_rows: usize,
+}
+
+impl Default for ExtractConfig {
+    fn default() -> Self {
+        Self {
+            csv_rows: default_csv_rows(),
+        }
+    }
+}
+
+fn default_csv_rows() -> usize {
+    20
+}
+
+/// Size limits for files attached to a prompt
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttachConfig {
+    #[serde(rename = "max_file_bytes", default = "default_max_file_bytes")]
+    pub max_file_bytes: usize,
+    #[serde(rename = "max_total_bytes", default = "default_max_total_bytes")]
+    pub max_total_bytes: usize,
+}
+
+impl Default for AttachConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: default_max_file_bytes(),
+            max_total_bytes: default_max_total_bytes(),
+        }
+    }
+}
+
+fn default_max_file_bytes() -> usize {
+    200_000
+}
+
+fn default_max_total_bytes() -> usize {
+    600_000
+}
+
+/// Map-reduce summarisation limits
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SummarizeConfig {
+    /// content larger than this (in characters) is summarised first
+    #[serde(rename = "context_chars", default = "default_context_chars")]
+    pub context_chars: usize,
+    /// size of each map chunk in characters
+    #[serde(rename = "chunk_chars", default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+}
+
+impl Default for SummarizeConfig {
+    fn default() -> Self {
+        Self {
+            context_chars: default_context_chars(),
+            chunk_chars: default_chunk_chars(),
+        }
+    }
+}
+
+fn default_context_chars() -> usize {
+    48000
+}
+
+fn default_chunk_chars() -> usize {
+    12000
+}
+
+/// Local vector index over the configured folders
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexConfig {
+    /// embedding model, when not set chunks are indexed without embeddings
+    #[serde(rename = "embedding_model", default)]
+    pub embedding_model: Option<String>,
+    /// defaults to api_url with /chat/completions replaced by /embeddings,
+    /// required with an embedding_model when api_url has another form
+    #[serde(rename = "embeddings_url", default)]
+    pub embeddings_url: Option<String>,
+    /// approximate chunk size in characters
+    #[serde(rename = "chunk_size", default = "default_chunk_size")]
+    pub chunk_size: usize,
+    /// number of chunks sent per embeddings request
+    #[serde(rename = "batch_size", default = "default_batch_size")]
+    pub batch_size: usize,
+    /// folder (relative to working_dir) searched by 'ask'
+    #[serde(rename = "documents_folder", default = "default_documents_folder")]
+    pub documents_folder: String,
+    /// number of chunks injected into an 'ask' prompt
+    #[serde(rename = "top_k", default = "default_retrieval_top_k")]
+    pub top_k: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            embedding_model: None,
+            embeddings_url: None,
+            chunk_size: default_chunk_size(),
+            batch_size: default_batch_size(),
+            documents_folder: default_documents_folder(),
+            top_k: default_retrieval_top_k(),
+        }
+    }
+}
+
+fn default_chunk_size() -> usize {
+    1200
+}
+
+fn default_batch_size() -> usize {
+    32
+}
+
+fn default_documents_folder() -> String {
+    "documents".to_string()
+}
+
+fn default_retrieval_top_k() -> usize {
+    6
+}
+
+/// A workspace folder, either "documents" (read-write) or
+/// {"name": "documents", "mode": "ro"}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "FolderEntry")]
+pub struct FolderConfig {
+    pub name: String,
+    pub mode: FolderMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FolderMode {
+    #[serde(rename = "ro")]
+    ReadOnly,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FolderEntry {
+    Name(String),
+    Full { name: String, mode: FolderMode },
+}
+
+impl From<FolderEntry> for FolderConfig {
+    fn from(entry: FolderEntry) -> Self {
+        match entry {
+            FolderEntry::Name(name) => Self {
+                name,
+                mode: FolderMode::ReadWrite,
+            },
+            FolderEntry::Full { name, mode } => Self { name, mode },
+        }
+    }
+}
+
+impl Spec {
+    pub fn folder_names(&self) -> Vec<String> {
+        self.folders.iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// canonical path relative to working_dir, unchanged when outside it
+    pub fn relative_path(&self, path: &Path) -> String {
+        let root = Path::new(&self.working_dir);
+        let root = root.canonicalize().unwrap_or(root.to_path_buf());
+        path.strip_prefix(&root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// settings for a (canonical) script path, defaults when not configured
+    pub fn script_config(&self, script: &Path) -> ScriptConfig {
+        self.scripts
+            .get(&self.relative_path(script))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Structured output settings, when set every completion is validated
+/// locally and the model is re-prompted with the errors on failure
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseFormatConfig {
+    /// one of json_object or json_schema
+    #[serde(rename = "type")]
+    pub format_type: String,
+    /// schema file relative to working_dir (json_schema only)
+    #[serde(rename = "schema_file", default)]
+    pub schema_file: Option<String>,
+    #[serde(rename = "max_retries", default = "default_max_retries")]
+    pub max_retries: usize,
+}
+
+fn default_max_retries() -> usize {
+    2
 }
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+/// schema summary (column name, inferred type, empty count) followed by
+/// the first rows as a markdown table
+pub fn extract(path: &Path, rows: usize) -> Result<String, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+    let mut kinds: Vec<Option<&str>> = vec![None; headers.len()];
+    let mut empty = vec![0; headers.len()];
+    let mut sample: Vec<Vec<String>> = vec![];
+    let mut total = 0;
+    for record in reader.records() {
+        let record = record?;
+        total += 1;
+        for (i, value) in record.iter().enumerate().take(headers.len()) {
+            if value.trim().is_empty() {
+                empty[i] += 1;
+                continue;
+            }
+            kinds[i] = Some(widen(kinds[i], infer(value)));
+        }
+        if sample.len() < rows {
+            sample.push(record.iter().map(|v| v.replace('|', "\\|")).collect());
+        }
+    }
+
+    let mut out = format!("CSV {} rows, {} columns\n\n", total, headers.len());
+    out.push_str("| column | type | empty |\n|---|---|---|\n");
+    for (i, header) in headers.iter().enumerate() {
+        out.push_str(&format!(
+            "| {} | {} | {} |\n",
+            header,
+            kinds[i].unwrap_or("empty"),
+            empty[i]
+        ));
+    }
+    out.push_str(&format!("\nFirst {} rows\n\n", sample.len()));
+    out.push_str(&format!("| {} |\n", headers.join(" | ")));
+    out.push_str(&format!("|{}\n", "---|".repeat(headers.len())));
+    for row in sample.iter() {
+        out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    Ok(out)
+}
+
+fn infer(value: &str) -> &'static str {
+    let value = value.trim();
+    if value.parse::<i64>().is_ok() {
+        "integer"
+    } else if value.parse::<f64>().is_ok() {
+        "float"
+    } else if matches!(value.to_lowercase().as_str(), "true" | "false") {
+        "boolean"
+    } else {
+        "text"
+    }
+}
+
+// the most general type seen so far wins
+fn widen(current: Option<&'static str>, next: &'static str) -> &'static str {
+    match (current, next) {
+        (None, next) => next,
+        (Some(a), b) if a == b => a,
+        (Some("integer"), "float") | (Some("float"), "integer") => "float",
+        _ => "text",
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// plain text from word/document.xml, paragraphs become lines
+pub fn extract(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| format!("[docx] no word/document.xml in archive : {}", e))?
+        .read_to_string(&mut xml)?;
+    Ok(xml_to_text(&xml))
+}
+
+// minimal scan of the wordprocessingml tags we care about, text runs
+// (w:t), tabs, breaks and paragraph ends
+fn xml_to_text(xml: &str) -> String {
+    let mut text = String::new();
+    let mut rest = xml;
+    let mut in_text = false;
+    while let Some(start) = rest.find('<') {
+        if in_text {
+            text.push_str(&unescape(&rest[..start]));
+        }
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        match (tag.starts_with('/'), name) {
+            (false, "w:t") => in_text = !tag.ends_with('/'),
+            (true, "w:t") => in_text = false,
+            (false, "w:tab") => text.push('\t'),
+            (false, "w:br") | (false, "w:cr") => text.push('\n'),
+            (true, "w:p") => text.push('\n'),
+            _ => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
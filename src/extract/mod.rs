@@ -0,0 +1,3 @@
+pub mod csv;
+pub mod docx;
+pub mod text;
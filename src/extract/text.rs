@@ -0,0 +1,91 @@
+use crate::cli::schema::Spec;
+use crate::extract::{csv, docx};
+use custom_logger as log;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// cached extractions, relative to working_dir
+pub const EXTRACT_CACHE_FOLDER: &str = ".index/extract";
+
+/// Text content of a file, PDF, DOCX, HTML and CSV files go through an
+/// extractor and the result is cached in .index/extract, everything else is
+/// read as utf-8
+pub fn read_text(path: &Path, spec: &Spec) -> Result<String, Box<dyn std::error::Error>> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "pdf" | "docx" | "html" | "htm" | "csv") {
+        return Ok(fs::read_to_string(path)?);
+    }
+
+    // the csv summary depends on csv_rows
+    let settings = match extension.as_str() {
+        "csv" => format!("csv_rows={}", spec.extract.csv_rows),
+        _ => String::new(),
+    };
+    let cache = cache_path(spec, path, &settings);
+    if is_fresh(path, &cache) {
+        log::debug!("[extract] using cached {}", cache.display());
+        return Ok(fs::read_to_string(&cache)?);
+    }
+    log::info!("[extract] extracting text from {}", path.display());
+    let text = match extension.as_str() {
+        "pdf" => extract_pdf(path)?,
+        "docx" => docx::extract(path)?,
+        "csv" => csv::extract(path, spec.extract.csv_rows)?,
+        _ => html2md::parse_html(&fs::read_to_string(path)?),
+    };
+    let written = cache
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| fs::write(&cache, &text));
+    if let Err(e) = written {
+        log::warn!("[extract] could not cache {}: {}", cache.display(), e);
+    }
+    Ok(text)
+}
+
+// the pdf parser panics on some malformed files, that should not take the
+// whole session down (i.e during /index)
+fn extract_pdf(path: &Path) -> Result<String, String> {
+    match std::panic::catch_unwind(|| pdf_extract::extract_text(path)) {
+        Ok(Ok(text)) => Ok(text),
+        Ok(Err(e)) => Err(format!("[extract] pdf '{}': {}", path.display(), e)),
+        Err(_) => {
+            log::warn!(
+                "[extract] skipping '{}', the pdf could not be parsed",
+                path.display()
+            );
+            Err(format!(
+                "[extract] pdf '{}': malformed file",
+                path.display()
+            ))
+        }
+    }
+}
+
+// i.e .index/extract/report.pdf-<hash of the full path and settings>.md,
+// outside the folders so globs and read only folders are not affected
+fn cache_path(spec: &Spec, path: &Path, settings: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let full = path.canonicalize().unwrap_or(path.to_path_buf());
+    let key = format!("{}\n{}", full.to_string_lossy(), settings);
+    let digest = format!("{:x}", Sha256::digest(key.as_bytes()));
+    Path::new(&spec.working_dir)
+        .join(EXTRACT_CACHE_FOLDER)
+        .join(format!("{}-{}.md", name, &digest[..16]))
+}
+
+fn is_fresh(source: &Path, cache: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(cache)) {
+        (Some(source), Some(cache)) => cache >= source,
+        _ => false,
+    }
+}
@@ -0,0 +1,91 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chunk {
+    /// 1 based, inclusive
+    #[serde(rename = "start_line")]
+    pub start_line: usize,
+    #[serde(rename = "end_line")]
+    pub end_line: usize,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embedding: Vec<f32>,
+}
+
+/// split text into line ranged chunks of roughly chunk_size characters,
+/// preferring to break on headings and blank lines (paragraphs). A line
+/// longer than chunk_size (minified json, one line logs) is cut into chunks
+/// of its own
+pub fn chunk_text(text: &str, chunk_size: usize) -> Vec<Chunk> {
+    let chunk_size = chunk_size.max(1);
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut start_line = 1;
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        if line.len() > chunk_size {
+            if !current.is_empty() {
+                push_chunk(&mut chunks, &current, start_line, line_no - 1);
+                current.clear();
+            }
+            for piece in split_line(line, chunk_size) {
+                push_chunk(&mut chunks, piece, line_no, line_no);
+            }
+            start_line = line_no + 1;
+            continue;
+        }
+        let is_boundary = line.trim().is_empty() || line.starts_with('#');
+        if !current.is_empty()
+            && (current.len() + line.len() > chunk_size
+                || (is_boundary && current.len() >= chunk_size / 2))
+        {
+            push_chunk(&mut chunks, &current, start_line, line_no - 1);
+            current.clear();
+            start_line = line_no;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.is_empty() {
+        let end_line = start_line + current.lines().count().max(1) - 1;
+        push_chunk(&mut chunks, &current, start_line, end_line);
+    }
+    chunks
+}
+
+fn push_chunk(chunks: &mut Vec<Chunk>, text: &str, start_line: usize, end_line: usize) {
+    // skip chunks that are only whitespace
+    if text.trim().is_empty() {
+        return;
+    }
+    chunks.push(Chunk {
+        start_line,
+        end_line,
+        text: text.to_string(),
+        embedding: vec![],
+    });
+}
+
+// pieces of at most max bytes, cut at char boundaries
+fn split_line(line: &str, max: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut rest = line;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        // a single char wider than max
+        if end == 0 {
+            end = rest
+                .chars()
+                .next()
+                .map(|c| c.len_utf8())
+                .unwrap_or(rest.len());
+        }
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    pieces.push(rest);
+    pieces
+}
@@ -0,0 +1,153 @@
+use crate::chat::client::ChatClient;
+use crate::chat::model::EmbeddingRequest;
+use crate::index::store::VectorIndex;
+use custom_logger as log;
+use std::collections::HashMap;
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    pub score: f32,
+}
+
+impl Hit {
+    /// source label used in prompts and citations i.e documents/foo.md:120-145
+    pub fn label(&self) -> String {
+        format!("{}:{}-{}", self.path, self.start_line, self.end_line)
+    }
+}
+
+/// top k chunks under the folder prefix, lexical (bm25) scores are combined
+/// with cosine similarity when the index holds embeddings
+pub async fn retrieve(
+    index: &VectorIndex,
+    client: &dyn ChatClient,
+    folder: &str,
+    query: &str,
+    k: usize,
+) -> Result<Vec<Hit>, Box<dyn std::error::Error>> {
+    let prefix = format!("{}/", folder.trim_end_matches('/'));
+    let mut hits: Vec<Hit> = index
+        .files
+        .iter()
+        .filter(|(path, _)| path.starts_with(&prefix))
+        .flat_map(|(path, entry)| {
+            entry.chunks.iter().map(move |c| Hit {
+                path: path.clone(),
+                start_line: c.start_line,
+                end_line: c.end_line,
+                text: c.text.clone(),
+                score: 0.0,
+            })
+        })
+        .collect();
+    if hits.is_empty() {
+        return Ok(hits);
+    }
+
+    let lexical = normalize(bm25(&hits, query));
+    let semantic = match &index.embedding_model {
+        Some(model) => {
+            let embeddings = client
+                .embed(EmbeddingRequest {
+                    model: model.clone(),
+                    input: vec![query.to_string()],
+                })
+                .await?;
+            let query_embedding = embeddings.into_iter().next().unwrap_or_default();
+            let vectors: Vec<&Vec<f32>> = index
+                .files
+                .iter()
+                .filter(|(path, _)| path.starts_with(&prefix))
+                .flat_map(|(_, entry)| entry.chunks.iter().map(|c| &c.embedding))
+                .collect();
+            Some(normalize(
+                vectors
+                    .iter()
+                    .map(|v| cosine(&query_embedding, v))
+                    .collect(),
+            ))
+        }
+        None => None,
+    };
+
+    for (i, hit) in hits.iter_mut().enumerate() {
+        hit.score = match &semantic {
+            Some(semantic) => 0.5 * lexical[i] + 0.5 * semantic[i],
+            None => lexical[i],
+        };
+    }
+    hits.retain(|h| h.score > 0.0);
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(k);
+    log::debug!("[retrieve] {} hit(s) for '{}'", hits.len(), query);
+    Ok(hits)
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.len() > 1)
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+fn bm25(hits: &[Hit], query: &str) -> Vec<f32> {
+    let docs: Vec<Vec<String>> = hits.iter().map(|h| tokenize(&h.text)).collect();
+    let n = docs.len() as f32;
+    let avg_len = docs.iter().map(|d| d.len()).sum::<usize>() as f32 / n;
+    let terms = tokenize(query);
+
+    let mut doc_freq: HashMap<&str, f32> = HashMap::new();
+    for term in terms.iter() {
+        let df = docs.iter().filter(|d| d.contains(term)).count() as f32;
+        doc_freq.insert(term, df);
+    }
+
+    docs.iter()
+        .map(|doc| {
+            let len = doc.len() as f32;
+            terms
+                .iter()
+                .map(|term| {
+                    let tf = doc.iter().filter(|t| *t == term).count() as f32;
+                    if tf == 0.0 {
+                        return 0.0;
+                    }
+                    let df = doc_freq[term.as_str()];
+                    let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                    idf * (tf * (BM25_K1 + 1.0))
+                        / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * len / avg_len.max(1.0)))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+// scale scores to 0..1 so lexical and semantic scores can be combined
+fn normalize(scores: Vec<f32>) -> Vec<f32> {
+    let max = scores.iter().cloned().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return scores.iter().map(|_| 0.0).collect();
+    }
+    scores.iter().map(|s| s.max(0.0) / max).collect()
+}
@@ -0,0 +1,259 @@
+use crate::chat::client::ChatClient;
+use crate::chat::model::EmbeddingRequest;
+use crate::cli::schema::Spec;
+use crate::extract::text::read_text;
+use crate::index::chunk::{Chunk, chunk_text};
+use custom_logger as log;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEntry {
+    pub mtime: u64,
+    pub hash: String,
+    pub chunks: Vec<Chunk>,
+}
+
+/// On disk (working_dir/.index/index.json) chunk and embedding store,
+/// keyed by path relative to working_dir
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VectorIndex {
+    #[serde(rename = "embedding_model")]
+    pub embedding_model: Option<String>,
+    pub updated: u64,
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexStatus {
+    pub files: usize,
+    pub chunks: usize,
+    pub embedded: usize,
+    pub new: usize,
+    pub changed: usize,
+    pub removed: usize,
+}
+
+impl VectorIndex {
+    pub fn path(working_dir: &str) -> PathBuf {
+        Path::new(working_dir).join(".index").join("index.json")
+    }
+
+    pub fn load(working_dir: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path(working_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(&path)?;
+        let index: VectorIndex = serde_json::from_str(&data)
+            .map_err(|e| format!("[index] corrupt index '{}': {}", path.display(), e))?;
+        Ok(index)
+    }
+
+    pub fn save(&self, working_dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path(working_dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// compare the index with the files on disk without modifying it
+    pub fn status(&self, spec: &Spec) -> IndexStatus {
+        let mut status = IndexStatus {
+            files: self.files.len(),
+            ..Default::default()
+        };
+        for entry in self.files.values() {
+            status.chunks += entry.chunks.len();
+            status.embedded += entry
+                .chunks
+                .iter()
+                .filter(|c| !c.embedding.is_empty())
+                .count();
+        }
+        let on_disk = workspace_files(spec, &spec.folder_names());
+        for (key, path) in on_disk.iter() {
+            match self.files.get(key) {
+                Some(entry) => {
+                    if modified(path) != entry.mtime {
+                        status.changed += 1;
+                    }
+                }
+                None => status.new += 1,
+            }
+        }
+        status.removed = self
+            .files
+            .keys()
+            .filter(|k| !on_disk.contains_key(*k))
+            .count();
+        status
+    }
+
+    /// incrementally re-index, files are only re-chunked (and re-embedded)
+    /// when both the mtime and content hash changed
+    pub async fn update(
+        &mut self,
+        client: &dyn ChatClient,
+        spec: &Spec,
+        rebuild: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update_folders(client, spec, rebuild, &spec.folder_names())
+            .await
+    }
+
+    /// update only the given folders, entries of the others are left as is
+    pub async fn update_folders(
+        &mut self,
+        client: &dyn ChatClient,
+        spec: &Spec,
+        rebuild: bool,
+        folders: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let model = spec.index.embedding_model.clone();
+        if rebuild || model != self.embedding_model {
+            log::info!("[index] full rebuild");
+            self.files.clear();
+            self.embedding_model = model.clone();
+        }
+        let on_disk = workspace_files(spec, folders);
+        self.files
+            .retain(|k, _| on_disk.contains_key(k) || !in_folders(k, folders));
+
+        let mut pending: Vec<String> = vec![];
+        for (key, path) in on_disk.iter() {
+            let mtime = modified(path);
+            if let Some(entry) = self.files.get(key)
+                && entry.mtime == mtime
+            {
+                continue;
+            }
+            // files without text content (i.e images) are skipped
+            let Ok(content) = read_text(path, spec) else {
+                log::debug!("[index] skipping non text file {}", key);
+                continue;
+            };
+            let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+            if let Some(entry) = self.files.get_mut(key)
+                && entry.hash == hash
+            {
+                entry.mtime = mtime;
+                continue;
+            }
+            log::debug!("[index] indexing {}", key);
+            self.files.insert(
+                key.clone(),
+                FileEntry {
+                    mtime,
+                    hash,
+                    chunks: chunk_text(&content, spec.index.chunk_size),
+                },
+            );
+            pending.push(key.clone());
+        }
+
+        if let Some(model) = model {
+            self.embed_pending(client, &model, spec.index.batch_size, &pending)
+                .await?;
+        }
+        self.updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        log::info!("[index] re-indexed {} file(s)", pending.len());
+        Ok(())
+    }
+
+    async fn embed_pending(
+        &mut self,
+        client: &dyn ChatClient,
+        model: &str,
+        batch_size: usize,
+        pending: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let refs: Vec<(String, usize)> = pending
+            .iter()
+            .flat_map(|key| {
+                let count = self.files.get(key).map(|e| e.chunks.len()).unwrap_or(0);
+                (0..count).map(move |i| (key.clone(), i))
+            })
+            .collect();
+        let total = refs.len();
+        for (batch_no, batch) in refs.chunks(batch_size.max(1)).enumerate() {
+            let input = batch
+                .iter()
+                .map(|(key, i)| self.files[key].chunks[*i].text.clone())
+                .collect();
+            let embeddings = client
+                .embed(EmbeddingRequest {
+                    model: model.to_string(),
+                    input,
+                })
+                .await?;
+            if embeddings.len() != batch.len() {
+                return Err(Box::from(format!(
+                    "[index] expected {} embeddings, got {}",
+                    batch.len(),
+                    embeddings.len()
+                )));
+            }
+            for ((key, i), embedding) in batch.iter().zip(embeddings) {
+                if let Some(entry) = self.files.get_mut(key) {
+                    entry.chunks[*i].embedding = embedding;
+                }
+            }
+            log::info!(
+                "[index] embedded {}/{} chunks",
+                (batch_no * batch_size.max(1) + batch.len()),
+                total
+            );
+        }
+        Ok(())
+    }
+}
+
+// all regular (non hidden) files under the given folders
+fn workspace_files(spec: &Spec, folders: &[String]) -> BTreeMap<String, PathBuf> {
+    let root = Path::new(&spec.working_dir);
+    let mut files = BTreeMap::new();
+    for folder in folders.iter() {
+        for entry in WalkDir::new(root.join(folder))
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|e| e.ok())
+        {
+            if entry.path().is_file() {
+                let key = entry
+                    .path()
+                    .strip_prefix(root)
+                    .unwrap_or(entry.path())
+                    .to_string_lossy()
+                    .to_string();
+                files.insert(key, entry.path().to_path_buf());
+            }
+        }
+    }
+    files
+}
+
+// index keys are relative to working_dir i.e documents/notes.md
+fn in_folders(key: &str, folders: &[String]) -> bool {
+    let path = Path::new(key);
+    folders.iter().any(|f| path.starts_with(f))
+}
+
+fn modified(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
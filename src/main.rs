@@ -1,15 +1,23 @@
 use crate::chat::process::ChatSession;
 use crate::cli::schema::ApplicationConfig;
+use crate::service::interrupt;
+use crate::service::workflow::run_cli;
 use crate::stt::process::execute;
+use crate::workspace::startup::prepare;
 use clap::Parser;
 use custom_logger as log;
 use std::{fs, str::FromStr};
 
 mod chat;
 mod cli;
+mod extract;
+mod index;
 mod prompt;
 mod service;
 mod stt;
+mod tools;
+mod workflow;
+mod workspace;
 
 // local modules
 use cli::schema::*;
@@ -53,20 +61,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if cfg.spec.model.is_empty() {
         return Err("[main] model cannot be empty".into());
     }
+    if cfg.spec.index.embedding_model.is_some()
+        && cfg.spec.index.embeddings_url.is_none()
+        && !cfg.spec.api_url.ends_with("/chat/completions")
+    {
+        return Err(
+            "[main] index.embeddings_url is required when api_url does not end in /chat/completions"
+                .into(),
+        );
+    }
 
     log::info!("[main] application : {}", env!("CARGO_PKG_NAME"));
     log::info!("[main] author      : {}", env!("CARGO_PKG_AUTHORS"));
     log::info!("[main] version     : {}", env!("CARGO_PKG_VERSION"));
 
-    // clean up all staging entries
-    fs::remove_dir_all(format!("{}/staging", cfg.spec.working_dir))?;
-    fs::create_dir_all(format!("{}/staging", cfg.spec.working_dir))?;
+    // create the workspace on first run, archive or purge previous outputs
+    prepare(&cfg.spec).map_err(|e| e.to_string())?;
     rustls::crypto::ring::default_provider()
         .install_default()
         .expect("Failed to install rustls crypto provider");
 
-    if args.stt {
-        let _res = execute(cfg).await;
+    // ctrl-c cancels the running script or model request, exits otherwise
+    interrupt::install();
+
+    if let Some(name) = args.workflow {
+        if let Err(e) = run_cli(cfg, name, &args.var).await {
+            log::error!("[main] {}", e);
+            return Err(Box::from(e.to_string()));
+        }
+    } else if args.stt {
+        if let Err(e) = execute(cfg).await {
+            log::error!("[main] {}", e);
+            return Err(Box::from(e.to_string()));
+        }
     } else {
         log::debug!("[main] using model: {}", cfg.spec.model);
         log::trace!("[main] connecting to API: {}", cfg.spec.api_url);
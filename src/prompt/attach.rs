@@ -0,0 +1,229 @@
+use crate::cli::schema::Spec;
+use crate::extract::text::read_text;
+use crate::workspace::sandbox::{Access, Sandbox};
+use custom_logger as log;
+use std::path::{Path, PathBuf};
+
+/// Result of expanding '@path', '@path#L10-80', '@glob' and
+/// 'open dir pattern close' references
+pub struct Attachments {
+    pub prompt: String,
+    pub attached: Vec<(String, usize)>,
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Resolve every file reference in the prompt (relative to working_dir and
+/// restricted to the configured folders) and append each file as a labelled
+/// fenced block, within the per file and total size limits
+pub fn attach(spec: &Spec, input: &str) -> Attachments {
+    let root = Path::new(&spec.working_dir);
+    let limits = &spec.attach;
+    let sandbox = Sandbox::new(spec);
+    // checked paths are canonical, labels stay relative to working_dir
+    let canonical_root = root.canonicalize().unwrap_or(root.to_path_buf());
+    let mut result = Attachments {
+        prompt: input.to_string(),
+        attached: vec![],
+        skipped: vec![],
+    };
+    let patterns = references(input);
+    if patterns.is_empty() {
+        return result;
+    }
+
+    let mut files: Vec<(PathBuf, Option<LineRange>)> = vec![];
+    for reference in patterns.iter() {
+        let (pattern, range) = split_range(reference);
+        let matches = expand(root, pattern);
+        if matches.is_empty() {
+            result
+                .skipped
+                .push((reference.clone(), "no matching files".to_string()));
+        }
+        for path in matches {
+            let path = match sandbox.check(&path, Access::Read) {
+                Ok(path) => path,
+                Err(err) => {
+                    result.skipped.push((reference.clone(), err.to_string()));
+                    continue;
+                }
+            };
+            if !files.iter().any(|(p, r)| *p == path && *r == range) {
+                files.push((path, range));
+            }
+        }
+    }
+
+    let mut total = 0;
+    let mut blocks = String::new();
+    for (path, range) in files.iter() {
+        let mut label = path
+            .strip_prefix(&canonical_root)
+            .or_else(|_| path.strip_prefix(root))
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string();
+        if let Some(range) = range {
+            label = format!("{}#L{}-{}", label, range.start, range.end);
+        }
+        let content = match read_text(path, spec) {
+            Ok(content) => match range {
+                Some(range) => range.slice(&content),
+                None => content,
+            },
+            Err(e) => {
+                result
+                    .skipped
+                    .push((label, format!("no text content ({})", e)));
+                continue;
+            }
+        };
+        // limits apply to the (extracted) text that ends up in the prompt
+        let size = content.len();
+        if size > limits.max_file_bytes {
+            result.skipped.push((
+                label,
+                format!(
+                    "{} bytes exceeds max_file_bytes {}",
+                    size, limits.max_file_bytes
+                ),
+            ));
+            continue;
+        }
+        if total + size > limits.max_total_bytes {
+            result.skipped.push((
+                label,
+                format!(
+                    "total would exceed max_total_bytes {}",
+                    limits.max_total_bytes
+                ),
+            ));
+            continue;
+        }
+        total += size;
+        blocks.push_str(&fenced(&label, &content));
+        result.attached.push((label, size));
+    }
+    if !blocks.is_empty() {
+        result.prompt = format!("{}\n\nAttached files:\n{}", input, blocks);
+    }
+    result
+}
+
+pub fn log_attachments(attachments: &Attachments) {
+    for (label, size) in attachments.attached.iter() {
+        log::info!("[attach] attached {} ({} bytes)", label, size);
+    }
+    for (label, reason) in attachments.skipped.iter() {
+        log::warn!("[attach] skipped {} : {}", label, reason);
+    }
+}
+
+// wrap content in a fence longer than any backtick run it contains
+pub fn fenced(label: &str, content: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("\n{} {}\n{}\n{}\n", fence, label, content.trim_end(), fence)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LineRange {
+    start: usize,
+    end: usize,
+}
+
+impl LineRange {
+    fn slice(&self, content: &str) -> String {
+        content
+            .lines()
+            .skip(self.start.saturating_sub(1))
+            .take(self.end + 1 - self.start.max(1))
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+}
+
+// 'documents/foo.md#L10-80' or 'documents/foo.md#L10'
+fn split_range(reference: &str) -> (&str, Option<LineRange>) {
+    let Some((path, fragment)) = reference.rsplit_once("#L") else {
+        return (reference, None);
+    };
+    let (start, end) = fragment.split_once('-').unwrap_or((fragment, fragment));
+    match (
+        start.parse::<usize>(),
+        end.trim_start_matches('L').parse::<usize>(),
+    ) {
+        (Ok(start), Ok(end)) if start >= 1 && end >= start => {
+            (path, Some(LineRange { start, end }))
+        }
+        _ => (reference, None),
+    }
+}
+
+// '@documents/specs/*.md' tokens and 'open documents *.yaml close' sections,
+// returned as glob patterns relative to working_dir
+fn references(input: &str) -> Vec<String> {
+    let mut patterns = vec![];
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        if let Some(reference) = word.strip_prefix('@') {
+            let reference = reference.trim_end_matches([',', ';', ':', ')', '?', '!']);
+            if !reference.is_empty() {
+                patterns.push(reference.to_string());
+            }
+        } else if word == "open" && words.get(i + 3) == Some(&"close") {
+            let dir = words[i + 1].trim_end_matches('/');
+            let name = words[i + 2];
+            let name = if is_glob(name) {
+                name.to_string()
+            } else {
+                format!("*{}*", name)
+            };
+            patterns.push(format!("{}/**/{}", dir, name));
+            i += 3;
+        }
+        i += 1;
+    }
+    patterns
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn expand(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let full = root.join(pattern);
+    if !is_glob(pattern) {
+        return if full.is_file() { vec![full] } else { vec![] };
+    }
+    // hidden files only when the pattern names them
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    };
+    match glob::glob_with(&full.to_string_lossy(), options) {
+        Ok(paths) => {
+            let mut matches: Vec<PathBuf> = paths
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .collect();
+            matches.sort();
+            matches
+        }
+        Err(e) => {
+            log::warn!("[attach] invalid pattern '{}': {}", pattern, e);
+            vec![]
+        }
+    }
+}
@@ -0,0 +1,620 @@
+use custom_logger as log;
+
+/// Typed commands produced by the prompt parser, anything that is not a
+/// slash command is sent to the model as is
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Prompt(String),
+    Execute {
+        script: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+        /// question sent to the model together with the run output
+        ask: Option<String>,
+        /// trailing '&', run as a job
+        background: bool,
+    },
+    Show,
+    Read(Option<String>),
+    Ask(String),
+    Summarize {
+        file: String,
+        instruction: Option<String>,
+    },
+    Index(IndexAction),
+    Tools,
+    Jobs,
+    JobTail {
+        id: usize,
+        lines: usize,
+    },
+    Kill(usize),
+    Run {
+        /// None lists the workflows
+        workflow: Option<String>,
+        vars: Vec<(String, String)>,
+    },
+    Blocks,
+    SaveBlock {
+        index: usize,
+        /// the block's path= hint when not given
+        path: Option<String>,
+    },
+    RunBlock {
+        index: usize,
+        /// send failures back to the model for a corrected block
+        fix: bool,
+    },
+    History,
+    Diff {
+        from: String,
+        to: String,
+    },
+    Edit,
+    Help,
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexAction {
+    Status,
+    Update,
+    Rebuild,
+}
+
+/// (usage, description) for each slash command, used by /help and errors
+pub const COMMANDS: &[(&str, &str)] = &[
+    (
+        "/execute <dir> <name>|<tool> [K=v..] [-- args] [and ask <q>|&]",
+        "execute a script or a tool from the manifest, '&' runs it as a job",
+    ),
+    ("/show", "console print current session content"),
+    (
+        "/read [file]",
+        "read a file (default 'staging/current.md') into the session",
+    ),
+    (
+        "/ask <question>",
+        "answer from the documents folder with citations",
+    ),
+    ("/summarize <file> [instruction]", "summarise a large file"),
+    (
+        "/index [update|rebuild]",
+        "show or refresh the workspace index",
+    ),
+    (
+        "/tools",
+        "reload and list the tools described in the tool manifest",
+    ),
+    ("/jobs", "list the background jobs of the session"),
+    ("/job <id> tail [lines]", "show the end of a job log"),
+    ("/kill <id>", "stop a background job"),
+    (
+        "/run [workflow] [K=v..]",
+        "run a workflow from the workflows folder (list them without a name)",
+    ),
+    (
+        "/blocks",
+        "list the fenced code blocks of the last response",
+    ),
+    (
+        "/save-block <n> [path]",
+        "write a code block to a file in the workspace",
+    ),
+    (
+        "/run-block <n> [fix]",
+        "run a shell or python block, 'fix' asks the model to correct failures",
+    ),
+    ("/history files", "list the saved responses"),
+    (
+        "/diff [a] [b]",
+        "unified diff of two responses by number, file or 'latest'",
+    ),
+    ("/edit", "edit the last prompt in $EDITOR and send it"),
+    ("/help", "show this menu"),
+    ("/exit", "quit"),
+];
+
+/// split input into tokens, single and double quotes group words and a
+/// backslash escapes the next character
+pub fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (_, '\\') if quote != Some('\'') => match chars.next() {
+                Some(next) => {
+                    current.push(next);
+                    in_token = true;
+                }
+                None => return Err("trailing escape character '\\'".to_string()),
+            },
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_token = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("unterminated quote {}", q));
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// parse a slash command, plain text is returned as Command::Prompt.
+/// Execute carries the unresolved '<dir>/<name>' search target
+pub fn parse_command(input: &str) -> Result<Command, String> {
+    let trimmed = input.trim();
+    if !trimmed.starts_with('/') {
+        return Ok(Command::Prompt(trimmed.to_string()));
+    }
+    let tokens = tokenize(trimmed)?;
+    let name = tokens[0].trim_start_matches('/');
+    let args = &tokens[1..];
+    let rest = || args.join(" ");
+    match name {
+        "execute" => {
+            // a trailing '&' (or 'name&') runs the script in the background
+            let mut args = args.to_vec();
+            let background = match args.last_mut() {
+                Some(last) if last == "&" => {
+                    args.pop();
+                    true
+                }
+                Some(last) if last.ends_with('&') => {
+                    last.pop();
+                    true
+                }
+                _ => false,
+            };
+            let args = args.as_slice();
+            // '... and ask <question>' ends the command
+            let (args, ask) = match args.windows(2).position(|w| w[0] == "and" && w[1] == "ask") {
+                Some(i) if i + 2 < args.len() => (&args[..i], Some(args[i + 2..].join(" "))),
+                Some(_) => return Err(usage("/execute")),
+                None => (args, None),
+            };
+            if background && ask.is_some() {
+                return Err(
+                    "'and ask' waits for the run, it cannot be combined with '&'".to_string(),
+                );
+            }
+            // everything after '--' is passed to the script as arguments
+            let (target, script_args) = match args.iter().position(|a| a == "--") {
+                Some(i) => (&args[..i], &args[i + 1..]),
+                None => (args, &args[args.len()..]),
+            };
+            // 'open dir name close' is still accepted, the keywords are optional
+            let mut env = vec![];
+            let mut words = vec![];
+            for word in target.iter().filter(|s| *s != "open" && *s != "close") {
+                match word.split_once('=') {
+                    Some((key, value)) => {
+                        validate_env_key(key)?;
+                        validate_argument(value)?;
+                        env.push((key.to_string(), value.to_string()));
+                    }
+                    None => words.push(word.as_str()),
+                }
+            }
+            for arg in script_args.iter() {
+                validate_argument(arg)?;
+            }
+            let script = match words.as_slice() {
+                [path] => path.to_string(),
+                [dir, name] => format!("{}/{}", dir.trim_end_matches('/'), name),
+                _ => return Err(usage("/execute")),
+            };
+            Ok(Command::Execute {
+                script,
+                args: script_args.to_vec(),
+                env,
+                ask,
+                background,
+            })
+        }
+        "show" => Ok(Command::Show),
+        "read" => Ok(Command::Read(args.first().cloned())),
+        "ask" => {
+            if args.is_empty() {
+                return Err(usage("/ask"));
+            }
+            Ok(Command::Ask(rest()))
+        }
+        "summarize" | "summarise" => match args.split_first() {
+            Some((file, instruction)) => Ok(Command::Summarize {
+                file: file.clone(),
+                instruction: if instruction.is_empty() {
+                    None
+                } else {
+                    Some(instruction.join(" "))
+                },
+            }),
+            None => Err(usage("/summarize")),
+        },
+        "index" => match args.first().map(|s| s.as_str()) {
+            None | Some("status") => Ok(Command::Index(IndexAction::Status)),
+            Some("update") => Ok(Command::Index(IndexAction::Update)),
+            Some("rebuild") => Ok(Command::Index(IndexAction::Rebuild)),
+            Some(other) => Err(format!(
+                "unknown option '{}' for /index, use update or rebuild",
+                other
+            )),
+        },
+        "tools" => Ok(Command::Tools),
+        "jobs" => Ok(Command::Jobs),
+        "job" => {
+            let id = job_id(args.first(), "/job")?;
+            let lines = match (args.get(1).map(|s| s.as_str()), args.get(2)) {
+                (None, None) | (Some("tail"), None) => TAIL_LINES,
+                (Some("tail"), Some(n)) => n.parse().map_err(|_| usage("/job"))?,
+                _ => return Err(usage("/job")),
+            };
+            Ok(Command::JobTail { id, lines })
+        }
+        "kill" => Ok(Command::Kill(job_id(args.first(), "/kill")?)),
+        "run" => {
+            let mut vars = vec![];
+            for arg in args.iter().skip(1) {
+                let (key, value) = arg.split_once('=').ok_or(usage("/run"))?;
+                vars.push((key.to_string(), value.to_string()));
+            }
+            Ok(Command::Run {
+                workflow: args.first().cloned(),
+                vars,
+            })
+        }
+        "edit" => Ok(Command::Edit),
+        "blocks" => Ok(Command::Blocks),
+        "save-block" => {
+            let index = args
+                .first()
+                .and_then(|n| n.parse().ok())
+                .ok_or(usage("/save-block"))?;
+            if args.len() > 2 {
+                return Err(usage("/save-block"));
+            }
+            Ok(Command::SaveBlock {
+                index,
+                path: args.get(1).cloned(),
+            })
+        }
+        "run-block" => match args {
+            [n] | [n, _] => {
+                let index = n.parse().map_err(|_| usage("/run-block"))?;
+                let fix = match args.get(1).map(|s| s.as_str()) {
+                    None => false,
+                    Some("fix") => true,
+                    Some(_) => return Err(usage("/run-block")),
+                };
+                Ok(Command::RunBlock { index, fix })
+            }
+            _ => Err(usage("/run-block")),
+        },
+        "history" => match args.first().map(|s| s.as_str()) {
+            None | Some("files") => Ok(Command::History),
+            Some(_) => Err(usage("/history")),
+        },
+        // no arguments compares the last two responses
+        "diff" => match args {
+            [] => Ok(Command::Diff {
+                from: "-2".to_string(),
+                to: "latest".to_string(),
+            }),
+            [from] => Ok(Command::Diff {
+                from: from.clone(),
+                to: "latest".to_string(),
+            }),
+            [from, to] => Ok(Command::Diff {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+            _ => Err(usage("/diff")),
+        },
+        "help" => Ok(Command::Help),
+        "exit" | "quit" => Ok(Command::Exit),
+        other => Err(format!(
+            "unknown command '/{}', available commands : {}",
+            other,
+            COMMANDS
+                .iter()
+                .map(|(usage, _)| usage.split(' ').next().unwrap_or(usage))
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )),
+    }
+}
+
+// variables that change how the script itself is loaded or interpreted
+const RESERVED_ENV: &[&str] = &["PATH", "IFS", "ENV", "BASH_ENV", "SHELLOPTS", "HOME"];
+
+/// named parameters become environment variables, keys have to be plain
+/// identifiers and must not override the loader or shell setup
+pub fn validate_env_key(key: &str) -> Result<(), String> {
+    let mut chars = key.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("invalid parameter name '{}'", key));
+    }
+    if RESERVED_ENV.contains(&key) || key.starts_with("LD_") {
+        return Err(format!("parameter '{}' is not allowed", key));
+    }
+    Ok(())
+}
+
+/// scripts are spawned without a shell, values are still rejected when they
+/// contain characters a script could end up interpreting (i.e unquoted $1)
+pub fn validate_argument(value: &str) -> Result<(), String> {
+    match value
+        .chars()
+        .find(|c| c.is_control() || "`$;&|<>(){}[]*?!~#\\'\"".contains(*c))
+    {
+        Some(c) => Err(format!(
+            "argument '{}' contains the unsafe character {:?}",
+            value, c
+        )),
+        None => Ok(()),
+    }
+}
+
+/// values rendered from workflow variables and step outputs are passed as
+/// is, model output nearly always has quotes or brackets, only what exec
+/// cannot carry is rejected
+pub fn validate_rendered(value: &str) -> Result<(), String> {
+    if value.contains('\0') {
+        return Err(format!("value '{}' contains a NUL byte", value));
+    }
+    Ok(())
+}
+
+// lines shown by '/job <id>' without a count
+const TAIL_LINES: usize = 20;
+
+fn job_id(arg: Option<&String>, name: &str) -> Result<usize, String> {
+    arg.and_then(|id| id.trim_start_matches('%').parse().ok())
+        .ok_or(usage(name))
+}
+
+fn usage(name: &str) -> String {
+    let usage = COMMANDS
+        .iter()
+        .find(|(usage, _)| usage.split(' ').next() == Some(name))
+        .map(|(usage, _)| *usage)
+        .unwrap_or(name);
+    format!("usage : {}", usage)
+}
+
+pub fn print_help() {
+    log::info!("menu :");
+    let width = COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+    for (usage, description) in COMMANDS.iter() {
+        log::info!("     : {:<width$} {}", usage, description);
+    }
+    log::info!("     : anything else is sent to the model as a prompt");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<String> {
+        tokenize(input).unwrap()
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace() {
+        assert_eq!(
+            tokens("  /execute   scripts\ttest "),
+            ["/execute", "scripts", "test"]
+        );
+        assert!(tokens("   ").is_empty());
+    }
+
+    #[test]
+    fn tokenize_quotes_group_words() {
+        assert_eq!(tokens(r#"a "b c" 'd e'"#), ["a", "b c", "d e"]);
+        // quotes inside a word join with it
+        assert_eq!(tokens(r#"ISSUE="ABC 1"x"#), ["ISSUE=ABC 1x"]);
+        // empty quotes are an (empty) token
+        assert_eq!(tokens(r#"a "" b"#), ["a", "", "b"]);
+        assert_eq!(tokens(r#""it's" 'say "hi"'"#), ["it's", r#"say "hi""#]);
+    }
+
+    #[test]
+    fn tokenize_escapes() {
+        assert_eq!(tokens(r"a\ b c"), ["a b", "c"]);
+        assert_eq!(tokens(r#""a \" b""#), [r#"a " b"#]);
+        // no escapes inside single quotes
+        assert_eq!(tokens(r"'a\b'"), [r"a\b"]);
+        assert_eq!(tokens(r"\'"), ["'"]);
+    }
+
+    #[test]
+    fn tokenize_errors() {
+        assert!(tokenize(r#"a "b"#).is_err());
+        assert!(tokenize("a 'b").is_err());
+        assert!(tokenize(r"a\").is_err());
+    }
+
+    fn execute(script: &str, args: &[&str], env: &[(&str, &str)], ask: Option<&str>) -> Command {
+        Command::Execute {
+            script: script.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ask: ask.map(|q| q.to_string()),
+            background: false,
+        }
+    }
+
+    fn background(command: Command) -> Command {
+        match command {
+            Command::Execute {
+                script,
+                args,
+                env,
+                ask,
+                ..
+            } => Command::Execute {
+                script,
+                args,
+                env,
+                ask,
+                background: true,
+            },
+            other => other,
+        }
+    }
+
+    #[test]
+    fn plain_text_is_a_prompt() {
+        assert_eq!(
+            parse_command("  what is 'this'  "),
+            Ok(Command::Prompt("what is 'this'".to_string()))
+        );
+    }
+
+    #[test]
+    fn execute_target() {
+        let test = Ok(execute("scripts/test", &[], &[], None));
+        assert_eq!(parse_command("/execute scripts test"), test);
+        assert_eq!(parse_command("/execute scripts/ test"), test);
+        assert_eq!(parse_command("/execute open scripts test close"), test);
+        assert_eq!(
+            parse_command("/execute scripts/test.sh"),
+            Ok(execute("scripts/test.sh", &[], &[], None))
+        );
+        assert!(parse_command("/execute").is_err());
+        assert!(parse_command("/execute a b c").is_err());
+    }
+
+    #[test]
+    fn execute_env_and_args() {
+        assert_eq!(
+            parse_command(r#"/execute scripts jira ISSUE=ABC-123 -- --verbose "sprint 42""#),
+            Ok(execute(
+                "scripts/jira",
+                &["--verbose", "sprint 42"],
+                &[("ISSUE", "ABC-123")],
+                None
+            ))
+        );
+        // words after '--' are arguments even when they look like K=v
+        assert_eq!(
+            parse_command("/execute scripts jira -- A=b"),
+            Ok(execute("scripts/jira", &["A=b"], &[], None))
+        );
+    }
+
+    #[test]
+    fn execute_rejects_unsafe_values() {
+        assert!(parse_command("/execute scripts jira -- '$(rm -rf /)'").is_err());
+        assert!(parse_command("/execute scripts jira X='a;b'").is_err());
+        assert!(parse_command("/execute scripts jira PATH=/tmp").is_err());
+        assert!(parse_command("/execute scripts jira LD_PRELOAD=x").is_err());
+        assert!(parse_command("/execute scripts jira 1X=y").is_err());
+    }
+
+    #[test]
+    fn execute_and_ask() {
+        assert_eq!(
+            parse_command("/execute scripts deploy -- staging and ask why did this fail"),
+            Ok(execute(
+                "scripts/deploy",
+                &["staging"],
+                &[],
+                Some("why did this fail")
+            ))
+        );
+        // a question is required
+        assert!(parse_command("/execute scripts deploy and ask").is_err());
+    }
+
+    #[test]
+    fn execute_background() {
+        assert_eq!(
+            parse_command("/execute scripts test &"),
+            Ok(background(execute("scripts/test", &[], &[], None)))
+        );
+        assert_eq!(
+            parse_command("/execute scripts test -- x&"),
+            Ok(background(execute("scripts/test", &["x"], &[], None)))
+        );
+        assert!(parse_command("/execute scripts test and ask why &").is_err());
+    }
+
+    #[test]
+    fn numbered_commands() {
+        assert_eq!(
+            parse_command("/job %2 tail 5"),
+            Ok(Command::JobTail { id: 2, lines: 5 })
+        );
+        assert_eq!(
+            parse_command("/job 2"),
+            Ok(Command::JobTail {
+                id: 2,
+                lines: TAIL_LINES
+            })
+        );
+        assert_eq!(parse_command("/kill 3"), Ok(Command::Kill(3)));
+        assert!(parse_command("/kill x").is_err());
+        assert_eq!(
+            parse_command("/run-block 2 fix"),
+            Ok(Command::RunBlock {
+                index: 2,
+                fix: true
+            })
+        );
+        assert!(parse_command("/run-block 2 now").is_err());
+    }
+
+    #[test]
+    fn diff_defaults() {
+        assert_eq!(
+            parse_command("/diff"),
+            Ok(Command::Diff {
+                from: "-2".to_string(),
+                to: "latest".to_string()
+            })
+        );
+        assert_eq!(
+            parse_command("/diff 1"),
+            Ok(Command::Diff {
+                from: "1".to_string(),
+                to: "latest".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert!(parse_command("/nope").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+use custom_logger as log;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::Receiver;
+
+/// Follow up questions to the user while a command is being processed,
+/// implemented for the console (REPL) and for speech-to-text
+#[async_trait]
+pub trait Interact: Send + Sync {
+    /// returns the index of the chosen option, None when cancelled
+    async fn choose(
+        &self,
+        title: &str,
+        options: &[String],
+    ) -> Result<Option<usize>, Box<dyn std::error::Error>>;
+
+    /// yes, no or always (for the rest of the session)
+    async fn approve(
+        &self,
+        title: &str,
+        details: &[String],
+    ) -> Result<Approval, Box<dyn std::error::Error>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Approval {
+    Yes,
+    No,
+    Always,
+}
+
+pub struct ConsoleInteract {}
+
+#[async_trait]
+impl Interact for ConsoleInteract {
+    async fn choose(
+        &self,
+        title: &str,
+        options: &[String],
+    ) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        print_options(title, options);
+        print!("select [1-{}] or enter to cancel> ", options.len());
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(parse_option(&input, options.len()))
+    }
+
+    async fn approve(
+        &self,
+        title: &str,
+        details: &[String],
+    ) -> Result<Approval, Box<dyn std::error::Error>> {
+        print_details(title, details);
+        print!("approve? [y/n/always]> ");
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(parse_approval(&input))
+    }
+}
+
+/// Reads the answer from the next utterance ('... send') i.e "option two"
+pub struct SpeechInteract {
+    utterances: Arc<Mutex<Receiver<String>>>,
+}
+
+impl SpeechInteract {
+    pub fn new(utterances: Arc<Mutex<Receiver<String>>>) -> Self {
+        Self { utterances }
+    }
+}
+
+#[async_trait]
+impl Interact for SpeechInteract {
+    async fn choose(
+        &self,
+        title: &str,
+        options: &[String],
+    ) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        print_options(title, options);
+        print!("say 'option <number> send' or 'cancel'> ");
+        io::stdout().flush()?;
+        let answer = self.utterances.lock().await.recv().await;
+        match answer {
+            Some(answer) => Ok(parse_option(&answer, options.len())),
+            None => Ok(None),
+        }
+    }
+
+    async fn approve(
+        &self,
+        title: &str,
+        details: &[String],
+    ) -> Result<Approval, Box<dyn std::error::Error>> {
+        print_details(title, details);
+        print!("say 'approve send', 'always approve send' or 'deny send'> ");
+        io::stdout().flush()?;
+        let answer = self.utterances.lock().await.recv().await;
+        Ok(answer.map(|a| parse_approval(&a)).unwrap_or(Approval::No))
+    }
+}
+
+fn print_details(title: &str, details: &[String]) {
+    println!();
+    println!("{}", title);
+    for detail in details.iter() {
+        println!("  {}", detail);
+    }
+}
+
+// the whole answer has to be one of the accepted replies, anything else
+// (i.e "do not approve") is a no
+fn parse_approval(input: &str) -> Approval {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+    match words.join(" ").as_str() {
+        "y" | "yes" | "approve" => Approval::Yes,
+        "always" | "always approve" => Approval::Always,
+        _ => Approval::No,
+    }
+}
+
+fn print_options(title: &str, options: &[String]) {
+    // we use println and not custom_logger to keep the list readable
+    println!();
+    println!("{}", title);
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}) {}", i + 1, option);
+    }
+}
+
+// accepts '2', 'two', 'option two', 'number 2' ...
+fn parse_option(input: &str, count: usize) -> Option<usize> {
+    const NUMBERS: &[&str] = &[
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    let selected = input.split_whitespace().find_map(|word| {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        match word.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => NUMBERS.iter().position(|n| *n == word).map(|n| n + 1),
+        }
+    });
+    match selected {
+        Some(n) if n >= 1 && n <= count => Some(n - 1),
+        Some(n) => {
+            log::warn!("[interact] option {} is out of range", n);
+            None
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approval_accepts_exact_replies() {
+        assert_eq!(parse_approval("y\n"), Approval::Yes);
+        assert_eq!(parse_approval("  Yes "), Approval::Yes);
+        assert_eq!(parse_approval("approve "), Approval::Yes);
+        assert_eq!(parse_approval("Approve."), Approval::Yes);
+        assert_eq!(parse_approval("always"), Approval::Always);
+        assert_eq!(parse_approval("always approve "), Approval::Always);
+    }
+
+    #[test]
+    fn approval_anything_else_is_no() {
+        for answer in [
+            "",
+            "n",
+            "no",
+            "deny",
+            "do not approve",
+            "don't approve",
+            "not ok",
+            "ok",
+            "yes no",
+            "never approve",
+            "approve deny",
+            "always deny",
+            "yesterday",
+        ] {
+            assert_eq!(parse_approval(answer), Approval::No, "{:?}", answer);
+        }
+    }
+
+    #[test]
+    fn option_numbers_and_words() {
+        assert_eq!(parse_option("2", 3), Some(1));
+        assert_eq!(parse_option("option two", 3), Some(1));
+        assert_eq!(parse_option("number 3.", 3), Some(2));
+        assert_eq!(parse_option("four", 3), None);
+        assert_eq!(parse_option("0", 3), None);
+        assert_eq!(parse_option("", 3), None);
+    }
+}
@@ -1 +1,4 @@
+pub mod attach;
+pub mod command;
+pub mod interact;
 pub mod parser;
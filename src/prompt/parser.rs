@@ -1,70 +1,123 @@
+use crate::cli::schema::Spec;
+use crate::prompt::attach::{attach, log_attachments};
+use crate::prompt::command::{Command, parse_command};
+use crate::prompt::interact::Interact;
+use crate::tools::manifest::ToolRegistry;
+use crate::workspace::sandbox::{Access, Sandbox};
 use custom_logger as log;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use std::path::Path;
 use walkdir::WalkDir;
 
+// maximum number of candidates offered in the picker
+const MAX_OPTIONS: usize = 9;
+
 #[allow(unused)]
 pub struct PromptParser {}
 
 impl PromptParser {
-    pub fn parse(working_dir: String, input: String) -> Result<String, Box<dyn std::error::Error>> {
-        let result: String;
-        match input.clone() {
-            x if x.contains(&"execute") => {
-                result = format!("execute {}", build_from_input(working_dir, input));
+    pub async fn parse(
+        spec: &Spec,
+        tools: &ToolRegistry,
+        input: String,
+        interact: &dyn Interact,
+    ) -> Result<Command, Box<dyn std::error::Error>> {
+        let command = parse_command(&input).map_err(|e| format!("[parse] {}", e))?;
+        match command {
+            Command::Execute {
+                script,
+                args,
+                env,
+                ask,
+                background,
+            } => Ok(Command::Execute {
+                script: build_from_input(spec, tools, &script, interact).await?,
+                args,
+                env,
+                ask,
+                background,
+            }),
+            Command::Prompt(text) => {
+                let attachments = attach(spec, &text);
+                log_attachments(&attachments);
+                Ok(Command::Prompt(attachments.prompt))
             }
-            _ => result = input,
+            _ => Ok(command),
         }
-        Ok(result)
     }
 }
 
-fn build_from_input(working_dir: String, input: String) -> String {
-    if input.contains("open") && !input.contains("close")
-        || !input.contains("open") && input.contains("close")
+// resolve '<dir>/<name>' to a file under working_dir/dir, candidates are
+// ranked by fuzzy score on the path, the user picks when there is no
+// single clear winner
+async fn build_from_input(
+    spec: &Spec,
+    tools: &ToolRegistry,
+    target: &str,
+    interact: &dyn Interact,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // a tool name from the manifest needs no search
+    if !target.contains('/')
+        && let Some(tool) = tools.by_name(target)
     {
-        log::warn!("[parse] please ensure you have 'open' and 'close' key words");
-        "none".to_string()
-    } else {
-        let start = input.split("open").nth(1);
-        let result = match start {
-            Some(value) => {
-                let end = value.split("close").nth(0);
-                match end {
-                    Some(end_value) => {
-                        log::trace!("[parse] sub_string {}", end_value);
-                        let vec_file: Vec<&str> = end_value.split(" ").collect();
-                        let mut file_result = String::new();
-                        let mut count = 0;
-                        for entry in WalkDir::new(format!("{}/{}", working_dir, vec_file[1]))
-                            .into_iter()
-                            .filter_map(|e| e.ok())
-                        {
-                            if entry.path().is_file() {
-                                let filename = entry.path().file_name().unwrap().to_string_lossy();
-                                if filename.contains(vec_file[2]) {
-                                    println!();
-                                    file_result = entry.path().to_string_lossy().to_string();
-                                    log::info!("[parse] found {}", file_result);
-                                    count += 1;
-                                }
-                            }
-                        }
-                        if count == 0 || count > 1 {
-                            log::warn!(
-                                "[parse] be more specific with dir and search filename i.e use -> 'open dir/subdir fine-grained-name close'"
-                            );
-                            "none".to_string()
-                        } else {
-                            file_result
-                        }
-                    }
-                    None => {
-                        log::warn!("[parse] please ensure you have 'open' and 'close' key words");
-                        "none".to_string()
-                    }
+        log::info!("[parse] found tool {}", tool.name);
+        return Ok(tool.path.to_string_lossy().to_string());
+    }
+    let (dir, name) = target.rsplit_once('/').unwrap_or(("", target));
+    let sandbox = Sandbox::new(spec);
+    // i.e '/execute ../../etc passwd' must not escape the workspace
+    let root = sandbox.check(&Path::new(&spec.working_dir).join(dir), Access::Read)?;
+    let matcher = SkimMatcherV2::default();
+    let mut candidates: Vec<(i64, String)> = vec![];
+    for entry in WalkDir::new(&root).into_iter().filter_map(|e| e.ok()) {
+        if entry.path().is_file() {
+            let relative = entry
+                .path()
+                .strip_prefix(&root)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            if let Some(score) = matcher.fuzzy_match(&relative, name) {
+                candidates.push((score, entry.path().to_string_lossy().to_string()));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    log::debug!("[parse] candidates {:?}", candidates);
+
+    match candidates.as_slice() {
+        [] => Err(Box::from(format!(
+            "[parse] no file matching '{}' found in '{}'",
+            name, dir
+        ))),
+        [(_, path)] => {
+            log::info!("[parse] found {}", path);
+            Ok(path.clone())
+        }
+        [(first, path), (second, _), ..] if is_clear_winner(*first, *second) => {
+            log::info!("[parse] found {} (best match)", path);
+            Ok(path.clone())
+        }
+        _ => {
+            let options: Vec<String> = candidates
+                .iter()
+                .take(MAX_OPTIONS)
+                .map(|(_, path)| path.clone())
+                .collect();
+            let title = format!("[parse] '{}' matches several files :", name);
+            match interact.choose(&title, &options).await? {
+                Some(idx) => {
+                    log::info!("[parse] selected {}", options[idx]);
+                    Ok(options[idx].clone())
                 }
+                None => Err(Box::from("[parse] no file selected".to_string())),
             }
-            None => input,
-        };
-        result
+        }
     }
 }
+
+// the best score has to clearly beat the runner up
+fn is_clear_winner(first: i64, second: i64) -> bool {
+    first >= second + second / 2 + 10
+}
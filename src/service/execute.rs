@@ -1,26 +1,38 @@
+use crate::chat::blocks::{CodeBlock, parse_blocks};
 use crate::chat::client::ChatClient;
-use crate::chat::model::{CompletionRequest, InputMessage};
-use crate::cli::schema::ApplicationConfig;
+use crate::chat::history::{History, InferenceMeta};
+use crate::chat::model::{CompletionRequest, InputMessage, ResponseFormat, ToolCall};
+use crate::chat::structured::StructuredOutput;
+use crate::cli::schema::{ApplicationConfig, FeedbackMode, ScriptConfig, SideEffect};
+use crate::extract::text::read_text;
+use crate::index::search::retrieve;
+use crate::index::store::VectorIndex;
+use crate::prompt::command::{Command, IndexAction, print_help};
+use crate::prompt::interact::{Approval, ConsoleInteract, Interact};
+use crate::service::jobs::{JobState, Jobs};
+use crate::service::runner::{ExitStatus, Output, ScriptRun, run_script};
+use crate::service::summarize::Summarizer;
+use crate::tools::manifest::ToolRegistry;
+use crate::workspace::approval::{ApprovalPolicy, ApprovalRequest};
+use crate::workspace::sandbox::{Access, Sandbox};
 use custom_logger as log;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
 use std::os::unix::fs::PermissionsExt;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-#[derive(Debug)]
-enum ExitStatus {
-    OK,
-    WARNING,
-    ERROR,
-}
-
 pub trait ExecuteInterface {
-    fn new(client: Arc<dyn ChatClient>, config: ApplicationConfig) -> Self;
+    fn new(
+        client: Arc<dyn ChatClient>,
+        config: ApplicationConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized;
     async fn process_task(
         &mut self,
-        input_command: String,
+        input_command: Command,
     ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
@@ -29,32 +41,94 @@ pub struct Execute {
     pub config: ApplicationConfig,
     pub memory_map: HashMap<String, String>,
     pub messages: Vec<InputMessage>,
+    pub sandbox: Sandbox,
+    pub approval: ApprovalPolicy,
+    pub interact: Arc<dyn Interact>,
+    pub jobs: Jobs,
+    pub tools: ToolRegistry,
+    /// response_format of the config, the schema is read once at startup
+    pub structured: Option<StructuredOutput>,
 }
 
 impl ExecuteInterface for Execute {
-    fn new(client: Arc<dyn ChatClient>, config: ApplicationConfig) -> Self {
-        let system_prompt = InputMessage {
-            role: "system".to_string(),
-            content: config.spec.system_prompt.clone(),
+    fn new(
+        client: Arc<dyn ChatClient>,
+        config: ApplicationConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let system_prompt = InputMessage::system(&config.spec.system_prompt);
+        let sandbox = Sandbox::new(&config.spec);
+        let approval = ApprovalPolicy::new(&config.spec.approval);
+        let tools = ToolRegistry::load(&config.spec).unwrap_or_else(|err| {
+            log::warn!("[execute] no tools loaded : {}", err);
+            ToolRegistry::default()
+        });
+        let structured = match &config.spec.response_format {
+            Some(cfg) => Some(StructuredOutput::new(&config.spec.working_dir, cfg)?),
+            None => None,
         };
-        return Execute {
+        return Ok(Execute {
             client,
+            sandbox,
+            approval,
+            tools,
+            structured,
+            interact: Arc::new(ConsoleInteract {}),
+            jobs: Jobs::default(),
             config,
             memory_map: HashMap::new(),
             messages: vec![system_prompt],
-        };
+        });
     }
 
     async fn process_task(
         &mut self,
-        input_command: String,
+        input_command: Command,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        match input_command.clone() {
-            x if x.contains("none") => {
-                log::warn!("[process_task] input command is not well formed");
+        match input_command {
+            Command::Index(action) => self.index_command(action).await,
+            Command::Summarize { file, instruction } => {
+                self.summarize(&file, instruction.as_deref()).await
+            }
+            Command::Ask(question) => self.ask(&question).await,
+            Command::Help => {
+                print_help();
+                Ok(())
+            }
+            Command::Tools => self.list_tools(),
+            Command::Jobs => {
+                self.list_jobs();
+                Ok(())
+            }
+            Command::JobTail { id, lines } => {
+                // preserve origin content (ie no log decorations)
+                println!("{}", self.jobs.tail(id, lines)?);
+                Ok(())
+            }
+            Command::Blocks => self.list_blocks(),
+            Command::SaveBlock { index, path } => self.save_block(index, path).await,
+            Command::RunBlock { index, fix } => self.run_block(index, fix).await,
+            Command::History => self.list_history(),
+            Command::Diff { from, to } => {
+                let diff = History::new(&self.config.spec).diff(&from, &to)?;
+                if diff.is_empty() {
+                    log::info!("[history] no differences");
+                } else {
+                    // preserve origin content (ie no log decorations)
+                    print!("{}", diff);
+                }
                 Ok(())
             }
-            x if x.contains("show") => {
+            Command::Run { workflow, vars } => self.run_workflow(workflow, &vars).await,
+            Command::Kill(id) => {
+                self.jobs.kill(id)?;
+                log::info!("[jobs] stopping job {}", id);
+                Ok(())
+            }
+            Command::Exit => Ok(()),
+            Command::Edit => Err(Box::from(
+                "[process_task] /edit is only available at the interactive prompt".to_string(),
+            )),
+            Command::Show => {
                 let res_data = self.memory_map.get("current");
                 match res_data {
                     Some(data) => {
@@ -64,20 +138,34 @@ impl ExecuteInterface for Execute {
                         log::warn!("[process_task] no 'current' content found");
                     }
                 }
+                if let Some(report) = self.memory_map.get("execute") {
+                    println!("{}", report);
+                }
                 Ok(())
             }
-            x if x.contains("read") => {
+            Command::Read(file) => {
+                let file = file.unwrap_or("staging/current.md".to_string());
+                let path = self.sandbox.check(
+                    &Path::new(&self.config.spec.working_dir).join(&file),
+                    Access::Read,
+                )?;
                 // we need to handle the error gracefully so we don't crash
-                let res_data = fs::read_to_string(format!(
-                    "{}/staging/current.md",
-                    self.config.spec.working_dir
-                ));
+                let res_data = read_text(&path, &self.config.spec);
                 match res_data {
                     Ok(data) => {
+                        log::info!("[process_task] succesfully read '{}' from disk", file);
+                        let data = if Summarizer::too_large(&self.config.spec, &data) {
+                            log::warn!(
+                                "[process_task] '{}' exceeds the context, summarising",
+                                file
+                            );
+                            Summarizer::new(self.client.as_ref(), &self.config.spec, "current")
+                                .summarize(&data, None)
+                                .await?
+                        } else {
+                            data
+                        };
                         self.memory_map.insert("current".to_string(), data);
-                        log::info!(
-                            "[process_task] succesfully read 'staging/current.md' from disk"
-                        );
                     }
                     Err(err) => {
                         log::warn!("{}", err.to_string().to_lowercase());
@@ -85,123 +173,823 @@ impl ExecuteInterface for Execute {
                 }
                 Ok(())
             }
-            x if x.contains("execute") => {
-                // we are confident the parser has the format <command> <file_path>
-                let script = input_command.split(" ").nth(1).unwrap();
-                let mut exit_status: Option<ExitStatus> = None;
-                let mut command = Command::new(format!("{}", script));
-                log::debug!("[process_task] service command to execute {:?}", command);
-
-                let cmd_res = command.stdout(Stdio::piped()).spawn();
-                match cmd_res {
-                    Ok(res) => {
-                        let mut out = res.stdout.unwrap();
-                        let mut reader = BufReader::new(&mut out);
-                        // we use println and not custom_logger to preserve original output
-                        println!();
-                        loop {
-                            let mut line = String::new();
-                            let num_bytes = reader.read_line(&mut line).unwrap();
-                            match line.clone() {
-                                x if x.contains("exit => 0") => {
-                                    if exit_status.is_none() {
-                                        exit_status = Some(ExitStatus::OK);
-                                    }
-                                }
-                                x if x.contains("exit => 1") => {
-                                    if exit_status.is_none() {
-                                        exit_status = Some(ExitStatus::WARNING);
-                                    }
-                                }
-                                _ => {
-                                    // dont set this in the loop
-                                    // it will always be set to ERROR
-                                }
-                            }
-                            if num_bytes == 0 {
-                                println!("=> end of stream\n");
-                                break;
-                            }
-                            print!("{}", line);
-                        }
-                        if exit_status.is_none() {
-                            exit_status = Some(ExitStatus::ERROR);
-                        }
-                        match exit_status.unwrap() {
-                            ExitStatus::OK => {
-                                log::info!(
-                                    "[process_task] service {} executed successfully",
-                                    script
-                                );
-                            }
-                            ExitStatus::WARNING => {
-                                let err = format!(
-                                    "[process_task] agent {} executed with warning",
-                                    script
-                                );
-                                log::warn!("{}", err);
-                                return Err(Box::from(err));
-                            }
-                            ExitStatus::ERROR => {
-                                let err = format!("[process_task] command failed : {} ", script);
-                                log::error!("[process_task] {}", err);
-                                return Err(Box::from(err));
-                            }
+            Command::Execute {
+                script,
+                args,
+                env,
+                background: true,
+                ..
+            } => {
+                let (path, script_config, command_line) =
+                    self.prepare(&script, &args, &env).await?;
+                let id = self.jobs.spawn(
+                    &self.config.spec,
+                    path,
+                    args,
+                    env,
+                    script_config,
+                    command_line,
+                )?;
+                log::info!(
+                    "[jobs] job {} started, output in 'staging/jobs/{}.log'",
+                    id,
+                    id
+                );
+                Ok(())
+            }
+            Command::Execute {
+                script,
+                args,
+                env,
+                ask,
+                background: false,
+            } => {
+                let run = self.execute(&script, &args, &env).await?;
+                let mut command_line = vec![self.config.spec.relative_path(Path::new(&script))];
+                command_line.extend(args.iter().cloned());
+                self.keep_feedback(&command_line.join(" "), &run, ask.is_some());
+                let result = check_status(&script, &run);
+                match ask {
+                    Some(question) => {
+                        if let Err(err) = result {
+                            log::error!("{}", err);
                         }
+                        self.prompt(question).await
                     }
-                    Err(err) => {
-                        let task_err = format!(
-                            "[process_task] command failed : {}",
-                            err.to_string().to_lowercase()
-                        );
-                        log::error!("[process_task] {}", task_err);
-                        return Err(Box::from(task_err));
-                    }
+                    None => result,
                 }
-                Ok(())
             }
-            _ => {
-                let res_content = self.memory_map.get("current");
-                let full_prompt = match res_content {
-                    Some(content) => {
-                        format!("{} {}", input_command.clone(), content)
+            Command::Prompt(input) => self.prompt(input).await,
+        }
+    }
+}
+
+// single quote arguments for display when they contain whitespace
+fn quote(arg: &str) -> String {
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        format!("'{}'", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+// WARNING and ERROR runs are returned as errors
+fn check_status(script: &str, run: &ScriptRun) -> Result<(), Box<dyn std::error::Error>> {
+    let mut detail = run.detail();
+    if let Some(line) = run.stderr.lines().rev().find(|l| !l.trim().is_empty()) {
+        detail = format!("{}, stderr : {}", detail, line.trim());
+    }
+    match run.status {
+        ExitStatus::OK => {
+            log::info!("[execute] {} executed successfully", script);
+            Ok(())
+        }
+        ExitStatus::WARNING => {
+            let err = format!("[execute] {} executed with warning ({})", script, detail);
+            log::warn!("{}", err);
+            Err(Box::from(err))
+        }
+        ExitStatus::ERROR => Err(Box::from(format!(
+            "[execute] command failed : {} ({})",
+            script, detail
+        ))),
+    }
+}
+
+const FIX_INSTRUCTIONS: &str = "The script below failed. Reply with a corrected \
+version of the whole script in a single fenced code block, same language, and a \
+short explanation of the fix.";
+
+const ASK_INSTRUCTIONS: &str = "Answer the question using only the sources below. \
+Each source starts with a label in square brackets. Cite every statement with the \
+label of the source it came from, exactly as written i.e [documents/foo.md:120-145]. \
+If the sources do not contain the answer, say so.";
+
+impl Execute {
+    /// where approvals are asked, the console by default
+    pub fn with_interact(mut self, interact: Arc<dyn Interact>) -> Self {
+        self.interact = interact;
+        self
+    }
+
+    fn set_user_message(&mut self, content: String) {
+        // 0 should be role = system
+        match self.messages.get(1) {
+            Some(_) => {
+                // dont alter role
+                self.messages[1].content = content
+            }
+            None => {
+                self.messages.push(InputMessage::user(content));
+            }
+        }
+    }
+
+    // send input to the model with the session content appended
+    async fn prompt(&mut self, input: String) -> Result<(), Box<dyn std::error::Error>> {
+        let res_content = self.memory_map.get("current");
+        let mut full_prompt = match res_content {
+            Some(content) => {
+                format!("{} {}", input, content)
+            }
+            None => input,
+        };
+        // output of the last script run, see execute.feedback, only the
+        // next prompt carries it
+        if let Some(report) = self.memory_map.remove("execute") {
+            full_prompt = format!("{}\n\n{}", full_prompt, report);
+        }
+        self.set_user_message(full_prompt);
+        log::debug!("[prompt] prompt {:?}", self.messages,);
+
+        let request = self.completion_request(
+            self.messages.clone(),
+            self.structured.as_ref().map(|s| s.response_format()),
+        );
+
+        let res = match self.config.spec.tools.expose {
+            Some(level) => self.complete_with_tools(request, level).await,
+            None => self.client.complete(request).await,
+        };
+        match res {
+            Ok(data) => {
+                self.save_inference("prompt", &self.messages[1].content, &data)?;
+                if let Some(structured) = self.structured.as_ref() {
+                    self.validate_structured(structured, data).await?;
+                }
+            }
+            Err(err) => {
+                log::error!("[prompt] {}", err.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // let the model call the exposed tools, each round of calls is answered
+    // with the run reports until it replies with content
+    async fn complete_with_tools(
+        &self,
+        request: CompletionRequest,
+        level: SideEffect,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let definitions = self.tools.definitions(level);
+        if definitions.is_empty() {
+            return self.client.complete(request).await;
+        }
+        let max_rounds = self.config.spec.tools.max_rounds;
+        let mut messages = request.messages.clone();
+        for round in 0..=max_rounds {
+            let message = self
+                .client
+                .complete_message(CompletionRequest {
+                    messages: messages.clone(),
+                    tools: Some(definitions.clone()),
+                    ..request.clone()
+                })
+                .await?;
+            let content = message.content.unwrap_or_default();
+            let calls = message.tool_calls.unwrap_or_default();
+            if calls.is_empty() || round == max_rounds {
+                if !calls.is_empty() {
+                    log::warn!("[tools] stopped after {} rounds of tool calls", max_rounds);
+                }
+                // preserve origin content (ie no log decorations)
+                println!("{}", content);
+                return Ok(content);
+            }
+            messages.push(InputMessage::tool_calls(&content, calls.clone()));
+            for call in calls.iter() {
+                log::info!(
+                    "[tools] model called {} {}",
+                    call.function.name,
+                    call.function.arguments
+                );
+                let result = match self.call_tool(level, call).await {
+                    Ok(report) => report,
+                    Err(err) => {
+                        log::warn!("[tools] {}", err);
+                        format!("error: {}", err)
                     }
-                    None => input_command.to_owned(),
                 };
-                // 0 should be role = system
-                match self.messages.get(1) {
-                    Some(_) => {
-                        // dont alter role
-                        self.messages[1].content = full_prompt
-                    }
-                    None => {
-                        self.messages.push(InputMessage::user(full_prompt));
-                    }
+                messages.push(InputMessage::tool_result(&call.id, result));
+            }
+        }
+        Err(Box::from("[tools] no response from the model".to_string()))
+    }
+
+    async fn call_tool(
+        &self,
+        level: SideEffect,
+        call: &ToolCall,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let tool = self
+            .tools
+            .by_name(&call.function.name)
+            .filter(|t| t.side_effect <= level)
+            .ok_or(format!("unknown tool '{}'", call.function.name))?;
+        let env = tool.env_from_arguments(&call.function.arguments)?;
+        let run = self
+            .execute(&tool.path.to_string_lossy(), &[], &env)
+            .await?;
+        Ok(run.report(&tool.name, self.config.spec.execute.feedback_chars))
+    }
+
+    // reloads the registry, new or edited scripts are picked up here
+    fn list_tools(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.tools = ToolRegistry::load(&self.config.spec)?;
+        if self.tools.tools.is_empty() {
+            log::warn!(
+                "[tools] no tools found in '{}' (tools.json or front-matter)",
+                self.config.spec.tools.folder
+            );
+            return Ok(());
+        }
+        for tool in self.tools.tools.iter() {
+            let required: Vec<&str> = tool.parameters["required"]
+                .as_array()
+                .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+                .unwrap_or_default();
+            let params: Vec<String> = tool.parameters["properties"]
+                .as_object()
+                .map(|p| {
+                    p.keys()
+                        .map(|k| {
+                            if required.contains(&k.as_str()) {
+                                k.clone()
+                            } else {
+                                format!("[{}]", k)
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            log::info!(
+                "[tools] {} ({}) {}",
+                tool.name,
+                tool.side_effect,
+                tool.description
+            );
+            log::info!(
+                "      : script  : {}",
+                self.config.spec.relative_path(&tool.path)
+            );
+            if !params.is_empty() {
+                log::info!("      : params  : {}", params.join(" "));
+            }
+            if !tool.env.is_empty() {
+                log::info!("      : env     : {}", tool.env.join(", "));
+            }
+            if let Some(timeout) = tool.timeout_secs {
+                log::info!("      : timeout : {}s", timeout);
+            }
+        }
+        Ok(())
+    }
+
+    // fenced blocks of the last response (staging/inference.md)
+    fn latest_blocks(&self) -> Result<Vec<CodeBlock>, Box<dyn std::error::Error>> {
+        let path = Path::new(&self.config.spec.working_dir).join("staging/inference.md");
+        let text = fs::read_to_string(&path).map_err(|_| {
+            "[blocks] no response yet, 'staging/inference.md' is missing".to_string()
+        })?;
+        Ok(parse_blocks(&text))
+    }
+
+    fn list_blocks(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let blocks = self.latest_blocks()?;
+        if blocks.is_empty() {
+            log::info!("[blocks] no code blocks in the last response");
+        }
+        for block in blocks.iter() {
+            let first_line: String = block
+                .content
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(60)
+                .collect();
+            log::info!(
+                "[blocks] {:>2} {:<8} {:<24} {} lines : {}",
+                block.index,
+                block.language.as_deref().unwrap_or("-"),
+                block.path.as_deref().unwrap_or("-"),
+                block.content.lines().count(),
+                first_line
+            );
+        }
+        Ok(())
+    }
+
+    async fn save_block(
+        &self,
+        index: usize,
+        path: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let blocks = self.latest_blocks()?;
+        let block = blocks.get(index.wrapping_sub(1)).ok_or(format!(
+            "[blocks] no block {} ({} found)",
+            index,
+            blocks.len()
+        ))?;
+        let file = path.or(block.path.clone()).ok_or(format!(
+            "[blocks] block {} has no path= hint, use /save-block {} <path>",
+            index, index
+        ))?;
+        let target = self.sandbox.check(
+            &Path::new(&self.config.spec.working_dir).join(&file),
+            Access::Write,
+        )?;
+        if target.exists() {
+            let title = format!("[blocks] overwrite '{}' ?", file);
+            let details = vec![format!(
+                "block   : {} ({} lines)",
+                index,
+                block.content.lines().count()
+            )];
+            if self.interact.approve(&title, &details).await? == Approval::No {
+                return Err(Box::from(format!("[blocks] '{}' left unchanged", file)));
+            }
+        }
+        block.save(&target)?;
+        log::info!(
+            "[blocks] saved block {} to '{}'{}",
+            index,
+            file,
+            if block.is_script() {
+                " (executable)"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+
+    // run a shell or python block of the last response, with fix each
+    // failure is sent to the model and the block of its reply run next
+    async fn run_block(
+        &mut self,
+        index: usize,
+        fix: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let blocks = self.latest_blocks()?;
+        let mut block = blocks
+            .get(index.wrapping_sub(1))
+            .ok_or(format!(
+                "[blocks] no block {} ({} found)",
+                index,
+                blocks.len()
+            ))?
+            .clone();
+        let max_rounds = if fix {
+            self.config.spec.blocks.max_fix_rounds
+        } else {
+            0
+        };
+        for round in 0..=max_rounds {
+            let run = self.run_code_block(&block).await?;
+            let label = format!("block {}", block.index);
+            self.keep_feedback(&label, &run, false);
+            if run.status == ExitStatus::OK || round == max_rounds {
+                if fix && run.status != ExitStatus::OK {
+                    log::warn!("[blocks] still failing after {} fix rounds", max_rounds);
                 }
-                log::debug!("[process_task] prompt {:?}", self.messages,);
-
-                let request = CompletionRequest {
-                    model: self.config.spec.model.clone(),
-                    messages: self.messages.clone(),
-                    top_p: self.config.spec.top_p,
-                    temperature: Some(self.config.spec.temperature),
-                    stream: self.config.spec.stream,
-                    max_tokens: self.config.spec.max_tokens,
-                };
+                return check_status(&label, &run);
+            }
+            log::warn!(
+                "[blocks] block {} failed ({}), asking for a fix ({}/{})",
+                block.index,
+                run.detail(),
+                round + 1,
+                max_rounds
+            );
+            let prompt = format!(
+                "{}\n\n```{}\n{}```\n\n{}",
+                FIX_INSTRUCTIONS,
+                block.language.as_deref().unwrap_or_default(),
+                block.content,
+                run.report(&label, self.config.spec.execute.feedback_chars)
+            );
+            let messages = vec![self.messages[0].clone(), InputMessage::user(prompt.clone())];
+            let data = self
+                .client
+                .complete(self.completion_request(messages, None))
+                .await?;
+            self.save_inference("fix", &prompt, &data)?;
+            block = parse_blocks(&data)
+                .into_iter()
+                .find(|b| b.interpreter().is_some())
+                .ok_or("[blocks] no shell or python block in the reply, stopping".to_string())?;
+        }
+        Ok(())
+    }
 
-                let res = self.client.complete(request).await;
-                match res {
-                    Ok(data) => {
-                        let file_name =
-                            format!("{}/staging/inference.md", self.config.spec.working_dir);
-                        fs::write(file_name.clone(), data)?;
-                        fs::set_permissions(file_name, fs::Permissions::from_mode(0o777))?;
+    // show the block and run it from staging/blocks like any script, the
+    // file is named after the content so 'always' approves only this code.
+    // It is removed again when the run does not start (i.e not approved)
+    async fn run_code_block(
+        &self,
+        block: &CodeBlock,
+    ) -> Result<ScriptRun, Box<dyn std::error::Error>> {
+        let script = block.script().ok_or(format!(
+            "[blocks] block {} is not a shell or python block ({})",
+            block.index,
+            block.language.as_deref().unwrap_or("no language")
+        ))?;
+        let digest = format!("{:x}", Sha256::digest(script.as_bytes()));
+        let mut name = format!("run-{}", &digest[..12]);
+        if let Some(ext) = Path::new(&block.file_name()).extension() {
+            name = format!("{}.{}", name, ext.to_string_lossy());
+        }
+        let path = self.sandbox.check(
+            &Path::new(&self.config.spec.working_dir)
+                .join("staging/blocks")
+                .join(name),
+            Access::Write,
+        )?;
+        CodeBlock {
+            content: script,
+            ..block.clone()
+        }
+        .save(&path)?;
+        // preserve origin content (ie no log decorations)
+        println!(
+            "\n[blocks] block {} ({})\n  {}",
+            block.index,
+            block.interpreter().unwrap_or_default(),
+            block.content.lines().collect::<Vec<&str>>().join("\n  ")
+        );
+        let run = self.execute(&path.to_string_lossy(), &[], &[]).await;
+        if run.is_err()
+            && let Err(err) = fs::remove_file(&path)
+        {
+            log::warn!("[blocks] failed to remove {} : {}", path.display(), err);
+        }
+        run
+    }
+
+    // the run report goes into the session context per execute.feedback,
+    // forced is '/execute ... and ask'
+    fn keep_feedback(&mut self, command_line: &str, run: &ScriptRun, forced: bool) {
+        let feedback = forced
+            || match self.config.spec.execute.feedback {
+                FeedbackMode::Off => false,
+                FeedbackMode::Failure => run.status != ExitStatus::OK,
+                FeedbackMode::Always => true,
+            };
+        if feedback {
+            let report = run.report(command_line, self.config.spec.execute.feedback_chars);
+            self.memory_map.insert("execute".to_string(), report);
+            log::info!("[process_task] run output added to the session context");
+        } else {
+            self.memory_map.remove("execute");
+        }
+    }
+
+    // replace staging/blocks with the blocks of the latest response
+    fn extract_blocks(&self, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let folder = Path::new(&self.config.spec.working_dir).join("staging/blocks");
+        if folder.exists() {
+            fs::remove_dir_all(&folder)?;
+        }
+        let blocks = parse_blocks(data);
+        for block in blocks.iter() {
+            block.save(&folder.join(block.file_name()))?;
+        }
+        if !blocks.is_empty() {
+            log::info!(
+                "[blocks] extracted {} code block(s) to 'staging/blocks'",
+                blocks.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn list_history(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = History::new(&self.config.spec).entries()?;
+        if entries.is_empty() {
+            log::info!("[history] no saved responses in 'staging/history'");
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let first_line = entry
+                .body
+                .lines()
+                .find(|l| !l.trim().is_empty())
+                .unwrap_or_default();
+            let preview: String = first_line.chars().take(60).collect();
+            match entry.meta.as_ref() {
+                Some(meta) => log::info!(
+                    "[history] {:>3} {} {:<10} {} {} tokens : {}",
+                    i + 1,
+                    entry.name,
+                    meta.kind,
+                    meta.model,
+                    meta.total_tokens
+                        .map(|t| t.to_string())
+                        .unwrap_or("?".to_string()),
+                    preview
+                ),
+                None => log::info!("[history] {:>3} {} : {}", i + 1, entry.name, preview),
+            }
+        }
+        Ok(())
+    }
+
+    fn list_jobs(&self) {
+        let jobs = self.jobs.list();
+        if jobs.is_empty() {
+            log::info!("[jobs] no jobs in this session");
+        }
+        for job in jobs.iter() {
+            let state = match &job.state {
+                JobState::Running => {
+                    format!("running {}s", job.started.elapsed().as_secs())
+                }
+                JobState::Finished(status, detail) => format!("{} ({})", status, detail),
+                JobState::Failed(err) => format!("failed : {}", err),
+            };
+            log::info!("[jobs] {} {} : {}", job.id, job.command_line, state);
+        }
+    }
+
+    /// run a script in the foreground, script is the path resolved by the
+    /// parser
+    pub async fn execute(
+        &self,
+        script: &str,
+        args: &[String],
+        env: &[(String, String)],
+    ) -> Result<ScriptRun, Box<dyn std::error::Error>> {
+        let (path, script_config, _) = self.prepare(script, args, env).await?;
+        println!();
+        let run = run_script(
+            &path,
+            args,
+            env,
+            &script_config,
+            &self.config.spec,
+            Output::Terminal,
+        )
+        .await?;
+        println!("=> end of stream\n");
+        log::debug!(
+            "[execute] captured {} bytes stdout, {} bytes stderr",
+            run.stdout.len(),
+            run.stderr.len()
+        );
+        Ok(run)
+    }
+
+    // checks and approval shared by foreground runs and jobs, returns the
+    // script path, its config and the command line shown to the user
+    async fn prepare(
+        &self,
+        script: &str,
+        args: &[String],
+        env: &[(String, String)],
+    ) -> Result<(PathBuf, ScriptConfig, String), Box<dyn std::error::Error>> {
+        // symlinks inside the folders could still point outside the workspace
+        let path = self.sandbox.check(Path::new(script), Access::Execute)?;
+        let mut script_config = self.config.spec.script_config(&path);
+        // the manifest entry, when there is one, has the last word
+        let tool = self.tools.by_path(&path);
+        if let Some(tool) = tool {
+            tool.validate(env).map_err(|e| format!("[execute] {}", e))?;
+            let missing = tool.missing_env(&script_config, env);
+            if !missing.is_empty() {
+                return Err(Box::from(format!(
+                    "[execute] '{}' requires environment {}",
+                    tool.name,
+                    missing.join(", ")
+                )));
+            }
+            script_config.timeout_secs = tool.timeout_secs.or(script_config.timeout_secs);
+        }
+        let relative = self.config.spec.relative_path(&path);
+        let mut command_line = vec![relative.clone()];
+        command_line.extend(args.iter().map(|a| quote(a)));
+        let request = ApprovalRequest {
+            key: tool.map(|t| t.name.clone()).unwrap_or(relative),
+            command_line: command_line.join(" "),
+            cwd: if self.config.spec.execute.isolation.enabled {
+                self.config.spec.working_dir.clone()
+            } else {
+                std::env::current_dir()?.to_string_lossy().to_string()
+            },
+            env: env.to_vec(),
+            side_effect: tool.map(|t| t.side_effect),
+            trusted: tool.is_some_and(|t| t.in_manifest),
+        };
+        self.approval
+            .check(&request, self.interact.as_ref())
+            .await?;
+        Ok((path, script_config, request.command_line))
+    }
+
+    // file is relative to working_dir
+    async fn summarize(
+        &self,
+        file: &str,
+        instruction: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.sandbox.check(
+            &Path::new(&self.config.spec.working_dir).join(file),
+            Access::Read,
+        )?;
+        let content = read_text(&path, &self.config.spec)
+            .map_err(|e| format!("[summarize] failed to read '{}': {}", path.display(), e))?;
+        let name = Path::new(file)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or("summary".to_string());
+        let summary = Summarizer::new(self.client.as_ref(), &self.config.spec, &name)
+            .summarize(&content, instruction)
+            .await?;
+        self.write_inference(&summary)?;
+        // preserve origin content (ie no log decorations)
+        println!("{}", summary);
+        log::info!("[summarize] saved 'staging/summaries/{}/final.md'", name);
+        Ok(())
+    }
+
+    // retrieval augmented answer over the documents folder
+    async fn ask(&mut self, question: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let spec = self.config.spec.clone();
+        let mut index = VectorIndex::load(&spec.working_dir)?;
+        // staging changes with every answer, only the searched folder is
+        // brought up to date
+        index
+            .update_folders(
+                self.client.as_ref(),
+                &spec,
+                false,
+                std::slice::from_ref(&spec.index.documents_folder),
+            )
+            .await?;
+        index.save(&spec.working_dir)?;
+
+        let hits = retrieve(
+            &index,
+            self.client.as_ref(),
+            &spec.index.documents_folder,
+            question,
+            spec.index.top_k,
+        )
+        .await?;
+        if hits.is_empty() {
+            return Err(Box::from(format!(
+                "[ask] no relevant content found in '{}'",
+                spec.index.documents_folder
+            )));
+        }
+
+        let mut prompt = ASK_INSTRUCTIONS.to_string();
+        for hit in hits.iter() {
+            prompt.push_str(&format!("\n\n[{}]\n{}", hit.label(), hit.text.trim_end()));
+        }
+        prompt.push_str(&format!("\n\nQuestion: {}", question));
+        self.set_user_message(prompt);
+        log::debug!("[ask] prompt {:?}", self.messages);
+
+        let request = self.completion_request(self.messages.clone(), None);
+        let data = self.client.complete(request).await?;
+        self.save_inference("ask", &self.messages[1].content, &data)?;
+
+        // we use println and not custom_logger so the paths can be opened
+        println!();
+        println!("sources (* cited):");
+        for hit in hits.iter() {
+            let label = hit.label();
+            let marker = if data.contains(&label) { "*" } else { " " };
+            println!("  {} {}", marker, label);
+        }
+        Ok(())
+    }
+
+    async fn index_command(&self, action: IndexAction) -> Result<(), Box<dyn std::error::Error>> {
+        let spec = &self.config.spec;
+        let mut index = VectorIndex::load(&spec.working_dir)?;
+        match action {
+            IndexAction::Update => {
+                index.update(self.client.as_ref(), spec, false).await?;
+                index.save(&spec.working_dir)?;
+            }
+            IndexAction::Rebuild => {
+                index.update(self.client.as_ref(), spec, true).await?;
+                index.save(&spec.working_dir)?;
+            }
+            IndexAction::Status => {}
+        }
+        let status = index.status(spec);
+        log::info!("[index] folders   : {}", spec.folder_names().join(", "));
+        log::info!(
+            "[index] embedding : {}",
+            index
+                .embedding_model
+                .clone()
+                .unwrap_or("disabled".to_string())
+        );
+        log::info!("[index] files     : {}", status.files);
+        log::info!(
+            "[index] chunks    : {} ({} embedded)",
+            status.chunks,
+            status.embedded
+        );
+        log::info!(
+            "[index] pending   : {} new, {} changed, {} removed",
+            status.new,
+            status.changed,
+            status.removed
+        );
+        Ok(())
+    }
+
+    pub fn completion_request(
+        &self,
+        messages: Vec<InputMessage>,
+        response_format: Option<ResponseFormat>,
+    ) -> CompletionRequest {
+        CompletionRequest {
+            response_format,
+            ..CompletionRequest::new(&self.config.spec, messages)
+        }
+    }
+
+    // inference.md always holds the last response, every response is also
+    // kept in staging/history with its settings and token usage
+    fn save_inference(
+        &self,
+        kind: &str,
+        prompt: &str,
+        data: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let meta = InferenceMeta::new(&self.config.spec, kind, prompt, self.client.last_usage());
+        let path = History::new(&self.config.spec).save(&meta, data)?;
+        log::debug!("[save_inference] saved {}", path.display());
+        self.write_inference(data)
+    }
+
+    // inference.md and the extracted blocks only, for responses already in
+    // the history (i.e summaries, see Summarizer)
+    fn write_inference(&self, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file_name = format!("{}/staging/inference.md", self.config.spec.working_dir);
+        fs::write(file_name.clone(), data)?;
+        fs::set_permissions(file_name, fs::Permissions::from_mode(0o777))?;
+        if self.config.spec.blocks.auto_extract {
+            self.extract_blocks(data)?;
+        }
+        Ok(())
+    }
+
+    // validate the response locally, re-prompting the model with the
+    // validation errors up to max_retries before giving up
+    async fn validate_structured(
+        &self,
+        structured: &StructuredOutput,
+        data: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut messages = self.messages.clone();
+        let mut content = data;
+        let mut attempt = 0;
+        loop {
+            match structured.validate(&content) {
+                Ok(value) => {
+                    let file_name =
+                        format!("{}/staging/inference.json", self.config.spec.working_dir);
+                    fs::write(file_name.clone(), serde_json::to_string_pretty(&value)?)?;
+                    fs::set_permissions(file_name, fs::Permissions::from_mode(0o777))?;
+                    log::info!(
+                        "[validate_structured] response validated, saved 'staging/inference.json'"
+                    );
+                    return Ok(());
+                }
+                Err(errors) => {
+                    for error in errors.iter() {
+                        log::warn!("[validate_structured] {}", error);
                     }
-                    Err(err) => {
-                        log::error!("[process_task] {}", err.to_string());
+                    if attempt >= structured.max_retries {
+                        return Err(Box::from(format!(
+                            "[validate_structured] response failed validation after {} retries",
+                            structured.max_retries
+                        )));
                     }
+                    attempt += 1;
+                    log::info!(
+                        "[validate_structured] re-prompting model (retry {} of {})",
+                        attempt,
+                        structured.max_retries
+                    );
+                    messages.push(InputMessage::assistant(&content));
+                    messages.push(InputMessage::user(structured.retry_prompt(&errors)));
+                    let request = self
+                        .completion_request(messages.clone(), Some(structured.response_format()));
+                    content = self.client.complete(request).await?;
+                    let prompt = messages
+                        .last()
+                        .map(|m| m.content.as_str())
+                        .unwrap_or_default();
+                    self.save_inference("structured", prompt, &content)?;
                 }
-                Ok(())
             }
         }
     }
@@ -0,0 +1,55 @@
+use custom_logger as log;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+// script runs and model requests currently waiting for ctrl-c
+static WAITING: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPT: OnceLock<Notify> = OnceLock::new();
+
+/// the one ctrl-c handler of the process, installed at startup. A script
+/// run or model request in progress is cancelled, with nothing running the
+/// program exits as it would without a handler
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                log::warn!("[interrupt] failed to listen for ctrl-c : {}", err);
+                return;
+            }
+            if WAITING.load(Ordering::SeqCst) == 0 {
+                println!();
+                std::process::exit(130);
+            }
+            interrupt().notify_waiters();
+        }
+    });
+}
+
+/// resolves on the next ctrl-c, never when the handler is not installed
+pub async fn interrupted() {
+    // created before counting so a ctrl-c in between is not missed
+    let notified = interrupt().notified();
+    let _waiting = Waiting::new();
+    notified.await;
+}
+
+fn interrupt() -> &'static Notify {
+    INTERRUPT.get_or_init(Notify::new)
+}
+
+// counts a waiter for as long as the interrupted() future is alive
+struct Waiting;
+
+impl Waiting {
+    fn new() -> Self {
+        WAITING.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        WAITING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
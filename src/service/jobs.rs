@@ -0,0 +1,189 @@
+use crate::cli::schema::{ScriptConfig, Spec};
+use crate::service::runner::{ExitStatus, Output, run_script};
+use custom_logger as log;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Running,
+    /// status and detail i.e "exit code 3"
+    Finished(ExitStatus, String),
+    /// the script could not be started
+    Failed(String),
+}
+
+/// A script started with '/execute ... &'
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    pub command_line: String,
+    pub log: PathBuf,
+    pub started: Instant,
+    pub state: JobState,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: usize,
+    jobs: Vec<Job>,
+    cancel: HashMap<usize, oneshot::Sender<()>>,
+    notify: Option<UnboundedSender<String>>,
+}
+
+/// Background jobs of the session, output goes to staging/jobs/<id>.log.
+/// Clones share the same table
+#[derive(Clone, Default)]
+pub struct Jobs {
+    table: Arc<Mutex<JobTable>>,
+}
+
+impl Jobs {
+    /// finished job notices are sent here instead of the log
+    pub fn subscribe(&self) -> UnboundedReceiver<String> {
+        let (sender, receiver) = unbounded_channel();
+        self.table.lock().unwrap().notify = Some(sender);
+        receiver
+    }
+
+    /// run an approved script in the background, returns the job id
+    pub fn spawn(
+        &self,
+        spec: &Spec,
+        path: PathBuf,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+        config: ScriptConfig,
+        command_line: String,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let folder = Path::new(&spec.working_dir).join("staging/jobs");
+        fs::create_dir_all(&folder)?;
+        let mut table = self.table.lock().unwrap();
+        table.next_id += 1;
+        let id = table.next_id;
+        let log_path = folder.join(format!("{}.log", id));
+        let mut file = File::create(&log_path)
+            .map_err(|e| format!("[jobs] failed to create {} : {}", log_path.display(), e))?;
+        writeln!(file, "$ {}\n", command_line)?;
+        let output_file = file.try_clone()?;
+        let (cancel, on_cancel) = oneshot::channel();
+        table.cancel.insert(id, cancel);
+        table.jobs.push(Job {
+            id,
+            command_line: command_line.clone(),
+            log: log_path.clone(),
+            started: Instant::now(),
+            state: JobState::Running,
+        });
+        drop(table);
+
+        let jobs = self.clone();
+        let spec = spec.clone();
+        tokio::spawn(async move {
+            let output = Output::Log {
+                file: output_file,
+                cancel: on_cancel,
+            };
+            let state = match run_script(&path, &args, &env, &config, &spec, output).await {
+                Ok(run) => JobState::Finished(run.status, run.detail()),
+                Err(err) => JobState::Failed(err.to_string()),
+            };
+            let footer = match &state {
+                JobState::Finished(status, detail) => format!("=> {} ({})", status, detail),
+                JobState::Failed(err) => format!("=> failed : {}", err),
+                JobState::Running => String::new(),
+            };
+            let _ = writeln!(file, "\n{}", footer);
+            jobs.finish(id, state);
+        });
+        Ok(id)
+    }
+
+    fn finish(&self, id: usize, state: JobState) {
+        let mut table = self.table.lock().unwrap();
+        table.cancel.remove(&id);
+        let Some(job) = table.jobs.iter_mut().find(|j| j.id == id) else {
+            return;
+        };
+        job.state = state.clone();
+        let notice = match &state {
+            JobState::Finished(status, detail) => format!(
+                "[jobs] job {} finished : {} ({}) {}",
+                id, status, detail, job.command_line
+            ),
+            JobState::Failed(err) => format!("[jobs] job {} failed : {}", id, err),
+            JobState::Running => return,
+        };
+        if let Some(notify) = table.notify.as_ref()
+            && notify.send(notice.clone()).is_ok()
+        {
+            return;
+        }
+        match state {
+            JobState::Finished(ExitStatus::OK, _) => log::info!("{}", notice),
+            JobState::Finished(ExitStatus::WARNING, _) => log::warn!("{}", notice),
+            _ => log::error!("{}", notice),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.table.lock().unwrap().jobs.clone()
+    }
+
+    pub fn running(&self) -> usize {
+        self.list()
+            .iter()
+            .filter(|j| j.state == JobState::Running)
+            .count()
+    }
+
+    /// last lines of the job log
+    pub fn tail(&self, id: usize, lines: usize) -> Result<String, Box<dyn std::error::Error>> {
+        let job = self
+            .list()
+            .into_iter()
+            .find(|j| j.id == id)
+            .ok_or(format!("[jobs] no job {}", id))?;
+        let content = fs::read_to_string(&job.log)?;
+        let all: Vec<&str> = content.lines().collect();
+        Ok(all[all.len().saturating_sub(lines)..].join("\n"))
+    }
+
+    /// stop a running job, the process group gets the same SIGTERM then
+    /// SIGKILL treatment as a timeout
+    pub fn kill(&self, id: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let mut table = self.table.lock().unwrap();
+        if !table.jobs.iter().any(|j| j.id == id) {
+            return Err(Box::from(format!("[jobs] no job {}", id)));
+        }
+        match table.cancel.remove(&id) {
+            Some(cancel) => {
+                let _ = cancel.send(());
+                Ok(())
+            }
+            None => Err(Box::from(format!(
+                "[jobs] job {} is not running or already stopping",
+                id
+            ))),
+        }
+    }
+
+    /// kill all running jobs and wait up to grace_secs for them to finish
+    pub async fn stop_all(&self, grace_secs: u64) {
+        let running: Vec<usize> = self.table.lock().unwrap().cancel.keys().cloned().collect();
+        for id in running {
+            let _ = self.kill(id);
+        }
+        let deadline = Instant::now() + Duration::from_secs(grace_secs + 1);
+        while Instant::now() < deadline && self.list().iter().any(|j| j.state == JobState::Running)
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
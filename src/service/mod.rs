@@ -1 +1,6 @@
 pub mod execute;
+pub mod interrupt;
+pub mod jobs;
+pub mod runner;
+pub mod summarize;
+pub mod workflow;
@@ -0,0 +1,595 @@
+use crate::cli::schema::{ExecuteConfig, ScriptConfig, Spec};
+use crate::prompt::attach::fenced;
+use crate::service::interrupt::interrupted;
+use crate::workspace::isolation::{Isolation, landlock_abi};
+use custom_logger as log;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::{Child, Command as ProcessCommand};
+use tokio::sync::oneshot;
+use tokio::task::{AbortHandle, JoinHandle};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitStatus {
+    OK,
+    WARNING,
+    ERROR,
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitStatus::OK => write!(f, "ok"),
+            ExitStatus::WARNING => write!(f, "warning"),
+            ExitStatus::ERROR => write!(f, "error"),
+        }
+    }
+}
+
+/// Outcome of a script run, stdout and stderr are captured separately
+#[derive(Debug, Clone)]
+pub struct ScriptRun {
+    pub status: ExitStatus,
+    /// None when the process was terminated by a signal
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// set when the run was stopped i.e "timed out after 600s"
+    pub killed: Option<String>,
+}
+
+impl ScriptRun {
+    /// i.e "exit code 3" or "timed out after 600s"
+    pub fn detail(&self) -> String {
+        match (&self.killed, self.code) {
+            (Some(reason), _) => reason.clone(),
+            (None, Some(code)) => format!("exit code {}", code),
+            (None, None) => "killed by signal".to_string(),
+        }
+    }
+
+    /// status and captured output as prompt context, each stream keeps its
+    /// tail (where errors usually are) to stay within budget characters
+    pub fn report(&self, command_line: &str, budget: usize) -> String {
+        let stderr_budget = if self.stdout.trim().is_empty() {
+            budget
+        } else {
+            budget / 2
+        };
+        let stderr = tail(&self.stderr, stderr_budget);
+        let stdout = tail(&self.stdout, budget.saturating_sub(stderr.len()));
+        let mut report = format!(
+            "Script run `{}` finished with status {} ({})\n",
+            command_line,
+            self.status,
+            self.detail()
+        );
+        if !stdout.trim().is_empty() {
+            report.push_str(&fenced("stdout", &stdout));
+        }
+        if !stderr.trim().is_empty() {
+            report.push_str(&fenced("stderr", &stderr));
+        }
+        report
+    }
+}
+
+// last budget characters of text, cut at a line boundary when possible
+fn tail(text: &str, budget: usize) -> String {
+    if text.len() <= budget {
+        return text.to_string();
+    }
+    let mut start = text.len() - budget;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let kept = &text[start..];
+    let kept = kept.split_once('\n').map(|(_, rest)| rest).unwrap_or(kept);
+    format!("[... {} bytes omitted]\n{}", text.len() - kept.len(), kept)
+}
+
+/// Where the script output goes and what cancels the run besides the timeout
+pub enum Output {
+    /// streamed to the terminal, ctrl-c cancels
+    Terminal,
+    /// written to a log file, a message on cancel stops the run (see /kill)
+    Log {
+        file: File,
+        cancel: oneshot::Receiver<()>,
+    },
+}
+
+// what ended the wait on the child
+enum Outcome {
+    Exited(std::process::ExitStatus),
+    TimedOut(u64),
+    Cancelled,
+}
+
+/// spawn the script in its own process group (isolated when configured),
+/// stream stdout and stderr to the output while capturing them and derive
+/// the status from the exit code. The group is killed on timeout or cancel
+pub async fn run_script(
+    path: &Path,
+    args: &[String],
+    env: &[(String, String)],
+    config: &ScriptConfig,
+    spec: &Spec,
+    output: Output,
+) -> Result<ScriptRun, Box<dyn std::error::Error>> {
+    let limits = &spec.execute;
+    let mut command = ProcessCommand::new(path);
+    if limits.isolation.enabled {
+        if limits.isolation.landlock && landlock_abi().is_none() {
+            return Err(Box::from(
+                "[run_script] landlock is not supported by this kernel, set execute.isolation.landlock to false"
+                    .to_string(),
+            ));
+        }
+        Isolation::new(spec, config).apply(&mut command);
+    }
+    command
+        .args(args)
+        .envs(&config.env)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // the terminal's ctrl-c goes to us only, we stop the whole group
+        .process_group(0)
+        .kill_on_drop(true);
+    log::debug!("[run_script] command to execute {:?}", command);
+
+    let mut child = command.spawn().map_err(|e| {
+        let isolation = if limits.isolation.enabled {
+            " (isolation enabled)"
+        } else {
+            ""
+        };
+        format!(
+            "[run_script] failed to start {}{} : {}",
+            path.display(),
+            isolation,
+            e
+        )
+    })?;
+    let (sink, cancel) = match output {
+        Output::Terminal => (None, None),
+        Output::Log { file, cancel } => (Some(Arc::new(Mutex::new(file))), Some(cancel)),
+    };
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| capture(out, false, limits.clone(), sink.clone()));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| capture(err, true, limits.clone(), sink.clone()));
+
+    // the group id, child.id() is gone once the leader is reaped
+    let pid = child.id();
+    let timeout_secs = config.timeout_secs.unwrap_or(limits.timeout_secs);
+    let outcome = tokio::select! {
+        res = child.wait() => Outcome::Exited(res?),
+        _ = expire(timeout_secs) => Outcome::TimedOut(timeout_secs),
+        _ = cancelled(cancel) => Outcome::Cancelled,
+    };
+    let (exit, killed) = match outcome {
+        Outcome::Exited(exit) => (exit, None),
+        Outcome::TimedOut(secs) => {
+            log::warn!("[run_script] {} timed out after {}s", path.display(), secs);
+            let exit = terminate(&mut child, limits.kill_grace_secs).await?;
+            (exit, Some(format!("timed out after {}s", secs)))
+        }
+        Outcome::Cancelled => {
+            log::warn!("[run_script] cancelling {}", path.display());
+            let exit = terminate(&mut child, limits.kill_grace_secs).await?;
+            (exit, Some("cancelled".to_string()))
+        }
+    };
+    let (stdout, stderr) = drain(pid, stdout, stderr, limits.kill_grace_secs).await;
+
+    let code = exit.code();
+    let mut status = status_from_code(code, config);
+    if killed.is_some() {
+        status = ExitStatus::ERROR;
+    }
+    if config.legacy_markers
+        && status != ExitStatus::ERROR
+        && let Some(marker) = legacy_marker(&stdout)
+    {
+        status = marker;
+    }
+    Ok(ScriptRun {
+        status,
+        code,
+        stdout,
+        stderr,
+        killed,
+    })
+}
+
+// a timeout of 0 never expires
+async fn expire(secs: u64) {
+    if secs == 0 {
+        std::future::pending::<()>().await;
+    }
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+}
+
+// ctrl-c for terminal runs, the cancel message otherwise. A dropped sender
+// does not cancel
+async fn cancelled(cancel: Option<oneshot::Receiver<()>>) {
+    match cancel {
+        Some(cancel) => {
+            if cancel.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+        None => interrupted().await,
+    }
+}
+
+// SIGTERM the process group, SIGKILL it when still running after the grace period
+async fn terminate(
+    child: &mut Child,
+    grace_secs: u64,
+) -> Result<std::process::ExitStatus, Box<dyn std::error::Error>> {
+    let Some(pid) = child.id() else {
+        return Ok(child.wait().await?);
+    };
+    signal_group(pid, libc::SIGTERM);
+    match tokio::time::timeout(Duration::from_secs(grace_secs), child.wait()).await {
+        Ok(exit) => Ok(exit?),
+        Err(_) => {
+            log::warn!(
+                "[run_script] process group {} ignored SIGTERM, killing",
+                pid
+            );
+            signal_group(pid, libc::SIGKILL);
+            Ok(child.wait().await?)
+        }
+    }
+}
+
+fn signal_group(pid: u32, signal: libc::c_int) {
+    // the group id is the child's pid, see process_group(0)
+    let res = unsafe { libc::kill(-(pid as libc::pid_t), signal) };
+    if res != 0 {
+        log::debug!(
+            "[run_script] signal {} to group {} : {}",
+            signal,
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+// stream the output to the terminal or the job log while capturing it
+fn capture<R>(
+    reader: R,
+    is_stderr: bool,
+    limits: ExecuteConfig,
+    sink: Option<Arc<Mutex<File>>>,
+) -> Capture
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let text = Arc::new(Mutex::new(String::new()));
+    let task = match sink {
+        None => tokio::spawn(to_terminal(reader, is_stderr, limits, text.clone())),
+        Some(file) => tokio::spawn(to_log(reader, limits, file, text.clone())),
+    };
+    Capture { text, task }
+}
+
+// we use print and not custom_logger to preserve the original output, lines
+// are read at most up to the byte limit and once a limit is hit the rest of
+// the stream is drained in chunks and dropped
+async fn to_terminal<R>(reader: R, is_stderr: bool, limits: ExecuteConfig, text: Arc<Mutex<String>>)
+where
+    R: AsyncRead + Unpin,
+{
+    let mut reader = BufReader::new(reader);
+    let mut captured = 0;
+    let mut line = vec![];
+    let mut chunk = [0u8; 8192];
+    let mut lines = 0;
+    let mut dropped = 0;
+    loop {
+        if dropped > 0 {
+            match reader.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(n) => dropped += n,
+                Err(err) => {
+                    log::warn!("[run_script] failed to read output : {}", err);
+                    break;
+                }
+            }
+            continue;
+        }
+        line.clear();
+        let room = limits.max_output_bytes.saturating_sub(captured);
+        let mut bounded = (&mut reader).take(room as u64 + 1);
+        match bounded.read_until(b'\n', &mut line).await {
+            Ok(0) => break,
+            Ok(n) => {
+                if lines >= limits.max_output_lines || room == 0 {
+                    dropped += n;
+                    continue;
+                }
+                // a line over the limit keeps what fits
+                let kept = n.min(room);
+                let line = String::from_utf8_lossy(&line[..kept]);
+                print_stream(&line, is_stderr);
+                text.lock().unwrap().push_str(&line);
+                captured += kept;
+                lines += 1;
+                dropped += n - kept;
+            }
+            Err(err) => {
+                log::warn!("[run_script] failed to read output : {}", err);
+                break;
+            }
+        }
+    }
+    if dropped > 0 {
+        let notice = format!(
+            "\n[output truncated after {} lines, {} bytes dropped]\n",
+            lines, dropped
+        );
+        print_stream(&notice, is_stderr);
+        text.lock().unwrap().push_str(&notice);
+    }
+}
+
+// jobs keep their whole output in the log, only the last max_output_bytes
+// stay in memory
+async fn to_log<R>(
+    mut reader: R,
+    limits: ExecuteConfig,
+    file: Arc<Mutex<File>>,
+    text: Arc<Mutex<String>>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut chunk = [0u8; 8192];
+    // an incomplete utf-8 sequence at the end of a chunk waits for the next
+    let mut pending = vec![];
+    let mut omitted = 0;
+    loop {
+        let n = match reader.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) => {
+                log::warn!("[run_script] failed to read output : {}", err);
+                break;
+            }
+        };
+        if let Err(err) = file.lock().unwrap().write_all(&chunk[..n]) {
+            log::warn!("[run_script] failed to write the log : {}", err);
+        }
+        pending.extend_from_slice(&chunk[..n]);
+        let complete = match std::str::from_utf8(&pending) {
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            _ => pending.len(),
+        };
+        let mut text = text.lock().unwrap();
+        text.push_str(&String::from_utf8_lossy(&pending[..complete]));
+        pending.drain(..complete);
+        if text.len() > limits.max_output_bytes {
+            let mut start = text.len() - limits.max_output_bytes;
+            while !text.is_char_boundary(start) {
+                start += 1;
+            }
+            text.replace_range(..start, "");
+            omitted += start;
+        }
+    }
+    if omitted > 0 {
+        let notice = format!("[{} bytes omitted, see the job log]\n", omitted);
+        text.lock().unwrap().insert_str(0, &notice);
+    }
+}
+
+// output of one stream, the text is shared with the reading task so what
+// was read is kept when the task has to be aborted
+struct Capture {
+    text: Arc<Mutex<String>>,
+    task: JoinHandle<()>,
+}
+
+fn print_stream(text: &str, is_stderr: bool) {
+    if is_stderr {
+        eprint!("{}", text);
+    } else {
+        print!("{}", text);
+    }
+}
+
+// wait for the output streams to close, processes the script left running
+// in its group (i.e 'cmd &') keep them open, the group is killed when they
+// are still open after the grace period. The output read so far is returned
+// in any case
+async fn drain(
+    pid: Option<u32>,
+    stdout: Option<Capture>,
+    stderr: Option<Capture>,
+    grace_secs: u64,
+) -> (String, String) {
+    let texts = [&stdout, &stderr].map(|c| c.as_ref().map(|c| c.text.clone()));
+    let tasks: Vec<JoinHandle<()>> = stdout.into_iter().chain(stderr).map(|c| c.task).collect();
+    let aborts: Vec<AbortHandle> = tasks.iter().map(|t| t.abort_handle()).collect();
+    let streams = async {
+        for task in tasks {
+            let _ = task.await;
+        }
+    };
+    tokio::pin!(streams);
+    // reading the last buffered output takes a moment even without a grace
+    let grace = Duration::from_secs(grace_secs.max(1));
+    if tokio::time::timeout(grace, &mut streams).await.is_err() {
+        if let Some(pid) = pid {
+            log::warn!(
+                "[run_script] processes left in group {} still hold the output, killing",
+                pid
+            );
+            signal_group(pid, libc::SIGKILL);
+        }
+        if tokio::time::timeout(grace, &mut streams).await.is_err() {
+            // i.e a daemon that left the group
+            log::warn!("[run_script] output still open, detaching from it");
+            for abort in aborts.iter() {
+                abort.abort();
+            }
+            streams.await;
+        }
+    }
+    let [stdout, stderr] = texts.map(|t| {
+        t.map(|t| std::mem::take(&mut *t.lock().unwrap()))
+            .unwrap_or_default()
+    });
+    (stdout, stderr)
+}
+
+fn status_from_code(code: Option<i32>, config: &ScriptConfig) -> ExitStatus {
+    match code {
+        Some(code) if config.exit_codes.ok.contains(&code) => ExitStatus::OK,
+        Some(code) if config.exit_codes.warning.contains(&code) => ExitStatus::WARNING,
+        _ => ExitStatus::ERROR,
+    }
+}
+
+// the first 'exit => 0' or 'exit => 1' line printed by the script
+fn legacy_marker(stdout: &str) -> Option<ExitStatus> {
+    stdout.lines().find_map(|line| {
+        if line.contains("exit => 0") {
+            Some(ExitStatus::OK)
+        } else if line.contains("exit => 1") {
+            Some(ExitStatus::WARNING)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn limits(max_output_bytes: usize, max_output_lines: usize) -> ExecuteConfig {
+        ExecuteConfig {
+            max_output_bytes,
+            max_output_lines,
+            ..ExecuteConfig::default()
+        }
+    }
+
+    async fn captured(data: Vec<u8>, limits: ExecuteConfig) -> String {
+        let capture = capture(Cursor::new(data), false, limits, None);
+        drain(None, Some(capture), None, 1).await.0
+    }
+
+    // output of a job, returns the log content and what was kept in memory
+    async fn logged(data: Vec<u8>, limits: ExecuteConfig, name: &str) -> (Vec<u8>, String) {
+        let path = std::env::temp_dir().join(format!("runner-{}-{}", name, std::process::id()));
+        let sink = Arc::new(Mutex::new(File::create(&path).unwrap()));
+        let capture = capture(Cursor::new(data), false, limits, Some(sink));
+        let (text, _) = drain(None, Some(capture), None, 1).await;
+        let log = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (log, text)
+    }
+
+    #[tokio::test]
+    async fn capture_within_limits() {
+        let text = captured(b"one\ntwo\nthree".to_vec(), limits(100, 10)).await;
+        assert_eq!(text, "one\ntwo\nthree");
+    }
+
+    #[tokio::test]
+    async fn capture_stops_at_the_line_limit() {
+        let text = captured(b"1\n2\n3\n4\n".to_vec(), limits(100, 2)).await;
+        assert_eq!(
+            text,
+            "1\n2\n\n[output truncated after 2 lines, 4 bytes dropped]\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn capture_bounds_output_without_newlines() {
+        let data = vec![b'y'; 4 * 1024 * 1024];
+        let text = captured(data, limits(100, 10)).await;
+        assert!(text.starts_with(&"y".repeat(100)));
+        assert!(text.ends_with(&format!(
+            "[output truncated after 1 lines, {} bytes dropped]\n",
+            4 * 1024 * 1024 - 100
+        )));
+        assert!(text.len() < 200);
+    }
+
+    #[tokio::test]
+    async fn capture_keeps_the_start_of_a_long_line() {
+        let text = captured(b"ok\nabcdefghij\nmore\n".to_vec(), limits(8, 10)).await;
+        assert!(text.starts_with("ok\nabcde\n[output truncated"));
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_output_of_detached_streams() {
+        use tokio::io::AsyncWriteExt;
+        // the writer stays open like a daemon holding the pipe
+        let (reader, mut writer) = tokio::io::duplex(64);
+        writer.write_all(b"started\n").await.unwrap();
+        let capture = capture(reader, false, limits(100, 10), None);
+        let (text, stderr) = drain(None, Some(capture), None, 0).await;
+        assert_eq!(text, "started\n");
+        assert_eq!(stderr, "");
+        drop(writer);
+    }
+
+    #[tokio::test]
+    async fn jobs_log_everything_and_keep_a_tail() {
+        let data: Vec<u8> = (1..=20000)
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect();
+        let (log, text) = logged(data.clone(), limits(100, 10), "job").await;
+        assert_eq!(log, data);
+        assert!(text.starts_with("["));
+        assert!(text.contains("bytes omitted, see the job log]\n"));
+        assert!(text.ends_with("line 20000\n"));
+        assert!(text.len() < 200);
+    }
+
+    #[tokio::test]
+    async fn jobs_keep_multibyte_characters_across_chunks() {
+        let data = "é".repeat(10000).into_bytes();
+        let (log, text) = logged(data.clone(), limits(1_000_000, 10), "utf8").await;
+        assert_eq!(log, data);
+        assert_eq!(text, "é".repeat(10000));
+    }
+
+    #[test]
+    fn report_keeps_the_tail() {
+        let run = ScriptRun {
+            status: ExitStatus::ERROR,
+            code: Some(2),
+            stdout: (1..=100).map(|i| format!("line {}\n", i)).collect(),
+            stderr: String::new(),
+            killed: None,
+        };
+        let report = run.report("scripts/test", 40);
+        assert!(
+            report
+                .starts_with("Script run `scripts/test` finished with status error (exit code 2)")
+        );
+        assert!(report.contains("line 100"));
+        assert!(!report.contains("line 1\n"));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::chat::client::ChatClient;
+use crate::chat::history::{History, InferenceMeta};
+use crate::chat::model::{CompletionRequest, InputMessage};
+use crate::cli::schema::Spec;
+use crate::index::chunk::chunk_text;
+use custom_logger as log;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAP_PROMPT: &str = "Summarise the following section of a larger document. \
+Keep every fact, name, number, error and decision that could matter later, drop repetition.";
+
+const REDUCE_PROMPT: &str = "The following are summaries of consecutive sections of one document. \
+Combine them into a single coherent summary, keeping the important details.";
+
+/// Map-reduce summarisation for content larger than the context window,
+/// intermediate results are kept in staging/summaries/<name>/
+pub struct Summarizer<'a> {
+    client: &'a dyn ChatClient,
+    spec: &'a Spec,
+    dir: PathBuf,
+}
+
+impl<'a> Summarizer<'a> {
+    pub fn new(client: &'a dyn ChatClient, spec: &'a Spec, name: &str) -> Self {
+        let dir = Path::new(&spec.working_dir)
+            .join("staging")
+            .join("summaries")
+            .join(name);
+        Self { client, spec, dir }
+    }
+
+    /// true when the content does not fit in the configured context
+    pub fn too_large(spec: &Spec, content: &str) -> bool {
+        content.len() > spec.summarize.context_chars
+    }
+
+    pub async fn summarize(
+        &self,
+        content: &str,
+        instruction: Option<&str>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let chunks = chunk_text(content, self.spec.summarize.chunk_chars);
+        log::info!(
+            "[summarize] {} chars split into {} chunk(s), results in {}",
+            content.len(),
+            chunks.len(),
+            self.dir.display()
+        );
+
+        // map
+        let mut summaries = vec![];
+        for (i, chunk) in chunks.iter().enumerate() {
+            log::info!(
+                "[summarize] map {}/{} (lines {}-{})",
+                i + 1,
+                chunks.len(),
+                chunk.start_line,
+                chunk.end_line
+            );
+            let summary = self
+                .complete("summarize-map", MAP_PROMPT, &chunk.text)
+                .await?;
+            fs::write(self.dir.join(format!("chunk-{:03}.md", i + 1)), &summary)?;
+            summaries.push(summary);
+        }
+
+        // reduce, in several levels when the summaries are still too large
+        let mut level = 1;
+        while summaries.len() > 1 && summaries.join("\n\n").len() > self.spec.summarize.chunk_chars
+        {
+            let groups = group(&summaries, self.spec.summarize.chunk_chars);
+            if groups.len() == summaries.len() {
+                // every summary is already at the limit, fold what we have
+                break;
+            }
+            let mut reduced = vec![];
+            for (i, batch) in groups.iter().enumerate() {
+                log::info!(
+                    "[summarize] reduce level {} {}/{}",
+                    level,
+                    i + 1,
+                    groups.len()
+                );
+                let summary = self
+                    .complete("summarize-reduce", REDUCE_PROMPT, batch)
+                    .await?;
+                fs::write(
+                    self.dir.join(format!("reduce-{}-{:03}.md", level, i + 1)),
+                    &summary,
+                )?;
+                reduced.push(summary);
+            }
+            summaries = reduced;
+            level += 1;
+        }
+
+        log::info!("[summarize] final reduce");
+        let final_prompt = match instruction {
+            Some(instruction) => format!("{}\n\n{}", REDUCE_PROMPT, instruction),
+            None => REDUCE_PROMPT.to_string(),
+        };
+        let result = if summaries.len() == 1 && instruction.is_none() {
+            summaries.remove(0)
+        } else {
+            self.complete("summarize", &final_prompt, &summaries.join("\n\n---\n\n"))
+                .await?
+        };
+        fs::write(self.dir.join("final.md"), &result)?;
+        Ok(result)
+    }
+
+    // every intermediate response is kept in the history under kind, none
+    // is printed (complete_message), the caller shows the final summary
+    async fn complete(
+        &self,
+        kind: &str,
+        instruction: &str,
+        content: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let prompt = format!("{}\n\n{}", instruction, content);
+        let messages = vec![
+            InputMessage::system(&self.spec.system_prompt),
+            InputMessage::user(prompt.clone()),
+        ];
+        let data = self
+            .client
+            .complete_message(CompletionRequest::new(self.spec, messages))
+            .await?
+            .content
+            .unwrap_or_default();
+        let meta = InferenceMeta::new(self.spec, kind, &prompt, self.client.last_usage());
+        History::new(self.spec).save(&meta, &data)?;
+        Ok(data)
+    }
+}
+
+// pack consecutive summaries into batches of at most max_chars
+fn group(summaries: &[String], max_chars: usize) -> Vec<String> {
+    let mut groups: Vec<String> = vec![];
+    let mut current = String::new();
+    for summary in summaries.iter() {
+        if !current.is_empty() && current.len() + summary.len() > max_chars {
+            groups.push(current);
+            current = String::new();
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n---\n\n");
+        }
+        current.push_str(summary);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+    groups
+}
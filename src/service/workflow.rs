@@ -0,0 +1,231 @@
+use crate::chat::client::{ChatClient, OpenAIClient};
+use crate::chat::history::{History, InferenceMeta};
+use crate::chat::model::InputMessage;
+use crate::cli::schema::ApplicationConfig;
+use crate::prompt::command::{validate_env_key, validate_rendered};
+use crate::service::execute::{Execute, ExecuteInterface};
+use crate::service::runner::ExitStatus;
+use crate::workflow::definition::{Step, Workflow, list, render};
+use crate::workspace::sandbox::Access;
+use custom_logger as log;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Run log of a workflow in staging/workflows/<name>-<unix time>.log,
+/// every line is also logged to the console
+struct RunLog {
+    file: File,
+    path: String,
+}
+
+impl RunLog {
+    fn new(working_dir: &str, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let folder = Path::new(working_dir).join("staging/workflows");
+        fs::create_dir_all(&folder)?;
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let path = folder.join(format!("{}-{}.log", name, secs));
+        Ok(Self {
+            file: File::create(&path)?,
+            path: format!("staging/workflows/{}-{}.log", name, secs),
+        })
+    }
+
+    fn line(&mut self, status: Option<ExitStatus>, text: &str) {
+        match status {
+            Some(ExitStatus::WARNING) => log::warn!("[workflow] {}", text),
+            Some(ExitStatus::ERROR) => log::error!("[workflow] {}", text),
+            _ => log::info!("[workflow] {}", text),
+        }
+        let _ = writeln!(self.file, "{}", text);
+    }
+
+    // step output, only written to the file
+    fn output(&mut self, label: &str, text: &str) {
+        if !text.trim().is_empty() {
+            let _ = writeln!(self.file, "--- {}\n{}", label, text.trim_end());
+        }
+    }
+}
+
+// None is a skipped step
+fn status_name(status: Option<ExitStatus>) -> String {
+    status
+        .map(|s| s.to_string())
+        .unwrap_or("skipped".to_string())
+}
+
+impl Execute {
+    /// run the steps of workflows/<name>.yaml in dependency order, fails
+    /// when a step ends with ERROR
+    pub async fn run_workflow(
+        &self,
+        name: Option<String>,
+        overrides: &[(String, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let spec = self.config.spec.clone();
+        let Some(name) = name else {
+            let names = list(&spec);
+            if names.is_empty() {
+                log::warn!("[workflow] no workflows found in 'workflows'");
+            }
+            for name in names {
+                match Workflow::load(&spec, &name) {
+                    Ok(workflow) => log::info!("[workflow] {} {}", name, workflow.description),
+                    Err(err) => log::warn!("{}", err),
+                }
+            }
+            return Ok(());
+        };
+        let workflow = Workflow::load(&spec, &name)?;
+        let mut vars: HashMap<String, String> = workflow.vars.clone().into_iter().collect();
+        vars.extend(overrides.iter().cloned());
+
+        let mut run_log = RunLog::new(&spec.working_dir, &name)?;
+        run_log.line(None, &format!("running '{}'", workflow.name));
+        let started = Instant::now();
+        let mut results: HashMap<String, Option<ExitStatus>> = HashMap::new();
+        for i in workflow.order()? {
+            let step = &workflow.steps[i];
+            // needs not named in 'when' have to be ok or warning
+            let ready = step.needs.iter().all(|need| {
+                let status = results.get(need).cloned().flatten();
+                match step.when.get(need) {
+                    Some(statuses) => statuses.contains(&status_name(status)),
+                    None => matches!(status, Some(ExitStatus::OK | ExitStatus::WARNING)),
+                }
+            });
+            if !ready {
+                run_log.line(None, &format!("step {} : skipped", step.id));
+                vars.insert(format!("steps.{}.status", step.id), status_name(None));
+                results.insert(step.id.clone(), None);
+                continue;
+            }
+            let step_started = Instant::now();
+            let (status, detail, output) = match self.run_step(step, &vars).await {
+                Ok(result) => result,
+                Err(err) => (ExitStatus::ERROR, err.to_string(), String::new()),
+            };
+            run_log.line(
+                Some(status),
+                &format!(
+                    "step {} : {} ({}) in {}s",
+                    step.id,
+                    status,
+                    detail,
+                    step_started.elapsed().as_secs()
+                ),
+            );
+            run_log.output(&step.id, &output);
+            if let Some(save) = step.save.as_ref()
+                && status != ExitStatus::ERROR
+            {
+                let save = render(save, &vars)?;
+                let path = self
+                    .sandbox
+                    .check(&Path::new(&spec.working_dir).join(&save), Access::Write)?;
+                fs::write(&path, &output)?;
+                run_log.line(None, &format!("step {} : saved '{}'", step.id, save));
+                vars.insert(format!("steps.{}.file", step.id), save);
+            }
+            vars.insert(format!("steps.{}.output", step.id), output);
+            vars.insert(format!("steps.{}.status", step.id), status.to_string());
+            results.insert(step.id.clone(), Some(status));
+        }
+
+        let failed: Vec<&str> = workflow
+            .steps
+            .iter()
+            .filter(|s| results.get(&s.id) == Some(&Some(ExitStatus::ERROR)))
+            .map(|s| s.id.as_str())
+            .collect();
+        let summary = format!(
+            "'{}' finished in {}s, log in '{}'",
+            workflow.name,
+            started.elapsed().as_secs(),
+            run_log.path
+        );
+        if failed.is_empty() {
+            run_log.line(None, &summary);
+            return Ok(());
+        }
+        run_log.line(Some(ExitStatus::ERROR), &summary);
+        Err(Box::from(format!(
+            "[workflow] '{}' failed at step {}",
+            workflow.name,
+            failed.join(", ")
+        )))
+    }
+
+    // status, detail and output (stdout or the model response) of a step
+    async fn run_step(
+        &self,
+        step: &Step,
+        vars: &HashMap<String, String>,
+    ) -> Result<(ExitStatus, String, String), Box<dyn std::error::Error>> {
+        let spec = &self.config.spec;
+        if let Some(template) = step.prompt.as_ref() {
+            let prompt = render(template, vars)?;
+            let messages = vec![
+                InputMessage::system(&spec.system_prompt),
+                InputMessage::user(prompt.clone()),
+            ];
+            let request = self.completion_request(messages, None);
+            let content = self.client.complete(request).await?;
+            // kept in the history, inference.md is left to the chat session
+            let meta = InferenceMeta::new(spec, "workflow", &prompt, self.client.last_usage());
+            History::new(spec).save(&meta, &content)?;
+            return Ok((ExitStatus::OK, "response".to_string(), content));
+        }
+
+        let target = render(step.run.as_deref().unwrap_or_default(), vars)?;
+        // a tool name or a script path relative to working_dir
+        let script = match self.tools.by_name(&target) {
+            Some(tool) if !target.contains('/') => tool.path.clone(),
+            _ => Path::new(&spec.working_dir).join(&target),
+        };
+        let mut args = vec![];
+        for arg in step.args.iter() {
+            let arg = render(arg, vars)?;
+            validate_rendered(&arg)?;
+            args.push(arg);
+        }
+        let mut env = vec![];
+        for (key, value) in step.env.iter() {
+            let value = render(value, vars)?;
+            validate_env_key(key)?;
+            validate_rendered(&value)?;
+            env.push((key.clone(), value));
+        }
+        let run = self.execute(&script.to_string_lossy(), &args, &env).await?;
+        Ok((run.status, run.detail(), run.stdout))
+    }
+}
+
+/// --workflow mode, runs a single workflow without the chat prompt
+pub async fn run_cli(
+    config: ApplicationConfig,
+    name: String,
+    vars: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut overrides = vec![];
+    for var in vars.iter() {
+        let (key, value) = var
+            .split_once('=')
+            .ok_or(format!("[workflow] --var expects KEY=value, got '{}'", var))?;
+        overrides.push((key.to_string(), value.to_string()));
+    }
+    let api_key = fs::read_to_string(config.spec.openai_key_path.clone())
+        .map_err(|e| format!("[workflow] failed to read API key file : {}", e))?
+        .trim()
+        .to_string();
+    let client: Arc<dyn ChatClient> = Arc::new(
+        OpenAIClient::new(api_key, config.spec.api_url.clone())
+            .with_embeddings_url(config.spec.index.embeddings_url.clone()),
+    );
+    let ep = Execute::new(client, config)?;
+    ep.run_workflow(Some(name), &overrides).await
+}
@@ -1,5 +1,6 @@
 use crate::chat::client::OpenAIClient;
 use crate::cli::schema::ApplicationConfig;
+use crate::prompt::interact::SpeechInteract;
 use crate::prompt::parser::PromptParser;
 use crate::service::execute::{Execute, ExecuteInterface};
 use bytes::{BufMut, Bytes, BytesMut};
@@ -19,6 +20,7 @@ use std::io::Write;
 use std::process;
 use std::sync::Arc;
 use std::thread;
+use tokio::sync::Mutex;
 
 macro_rules! create_stream {
     ($device:ident, $config:expr, $sync_tx:ident, $sample_type:ty) => {
@@ -80,11 +82,24 @@ fn microphone_as_stream() -> FuturesReceiver<Result<Bytes, RecvError>> {
     async_rx
 }
 
+// commands are spoken as 'slash execute scripts test', plain speech is a prompt
+fn spoken_command(input: &str) -> String {
+    let trimmed = input.trim();
+    match trimmed.strip_prefix("slash ") {
+        Some(rest) => format!("/{}", rest),
+        None => trimmed.to_string(),
+    }
+}
+
 pub async fn execute(config: ApplicationConfig) -> Result<(), DeepgramError> {
     let deepgram_api_key = fs::read_to_string(format!("{}", config.spec.deepgram_key_path))?;
     let api_key = fs::read_to_string(format!("{}", config.spec.openai_key_path))?;
-    let client = Arc::new(OpenAIClient::new(api_key, config.spec.api_url.clone()));
-    let mut ep = Execute::new(client, config.clone());
+    let client = Arc::new(
+        OpenAIClient::new(api_key, config.spec.api_url.clone())
+            .with_embeddings_url(config.spec.index.embeddings_url.clone()),
+    );
+    let mut ep =
+        Execute::new(client, config.clone()).map_err(|e| std::io::Error::other(e.to_string()))?;
     let dg_client = Deepgram::new(&deepgram_api_key.trim())?;
     let mut results = dg_client
         .transcription()
@@ -98,67 +113,88 @@ pub async fn execute(config: ApplicationConfig) -> Result<(), DeepgramError> {
 
     log::info!("[execute] using speech-to-text service");
     log::info!("[execute] deepgram request id: {}", results.request_id());
-    println!();
-    print!("prompt> ");
-    std::io::stdout().flush().unwrap();
-    let mut input = String::new();
-    let mut exit = false;
-    while let Some(result) = results.next().await {
-        let response = serde_json::to_value(&result.unwrap()).unwrap();
-        let alternatives = response
-            .get("channel")
-            .unwrap()
-            .get("alternatives")
-            .unwrap();
-        let words = alternatives[0].get("words").unwrap().as_array().unwrap();
-        for word in words.iter() {
-            if word.is_object() {
-                let obj: Word = serde_json::from_value(word.clone()).unwrap();
-                match obj.word.as_str() {
-                    "send" => {
-                        let res_input_command =
-                            PromptParser::parse(config.spec.working_dir.clone(), input);
-                        match res_input_command {
-                            Ok(input_command) => {
-                                let res = ep.process_task(input_command.clone()).await;
-                                match res {
-                                    Ok(_data) => {}
-                                    Err(err) => {
-                                        log::error!("{}", err.to_string());
-                                    }
-                                }
+
+    // transcription runs in its own task so follow up questions (i.e picking
+    // a file) can wait for the next utterance while a command is processed
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(8);
+    let utterances = Arc::new(Mutex::new(rx));
+    let interact = Arc::new(SpeechInteract::new(utterances.clone()));
+    // approvals are spoken too ('approve send' / 'deny send')
+    ep = ep.with_interact(interact.clone());
+    tokio::spawn(async move {
+        let mut input = String::new();
+        while let Some(result) = results.next().await {
+            let response = serde_json::to_value(&result.unwrap()).unwrap();
+            let alternatives = response
+                .get("channel")
+                .unwrap()
+                .get("alternatives")
+                .unwrap();
+            let words = alternatives[0].get("words").unwrap().as_array().unwrap();
+            for word in words.iter() {
+                if word.is_object() {
+                    let obj: Word = serde_json::from_value(word.clone()).unwrap();
+                    match obj.word.as_str() {
+                        "send" => {
+                            if tx.send(std::mem::take(&mut input)).await.is_err() {
+                                return;
                             }
-                            Err(err) => {
-                                log::warn!("{}", err.to_string());
+                        }
+                        "cancel" => {
+                            println!("cancelled");
+                            input = String::new();
+                            if tx.send(String::new()).await.is_err() {
+                                return;
                             }
                         }
-                        println!();
-                        print!("prompt> ");
-                        std::io::stdout().flush().unwrap();
-                        input = String::new();
-                    }
-                    "cancel" => {
-                        println!("cancelled");
-                        print!("prompt> ");
-                        std::io::stdout().flush().unwrap();
-                        input = String::new();
-                    }
-                    "exit" => {
-                        println!();
-                        log::warn!("[execute] exiting speech to text service");
-                        exit = true;
-                        break;
-                    }
-                    _ => {
-                        print!("{} ", obj.word);
-                        std::io::stdout().flush().unwrap();
-                        input.push_str(&format!("{} ", obj.word));
+                        "exit" => {
+                            println!();
+                            log::warn!("[execute] exiting speech to text service");
+                            // dropping the sender ends the prompt loop
+                            return;
+                        }
+                        _ => {
+                            print!("{} ", obj.word);
+                            std::io::stdout().flush().unwrap();
+                            input.push_str(&format!("{} ", obj.word));
+                        }
                     }
                 }
             }
         }
-        if exit {
+    });
+
+    loop {
+        println!();
+        print!("prompt> ");
+        std::io::stdout().flush().unwrap();
+        let next = utterances.lock().await.recv().await;
+        let Some(input) = next else {
             break;
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        let res_input_command = PromptParser::parse(
+            &config.spec,
+            &ep.tools,
+            spoken_command(&input),
+            interact.as_ref(),
+        )
+        .await;
+        match res_input_command {
+            Ok(input_command) => {
+                let res = ep.process_task(input_command).await;
+                match res {
+                    Ok(_data) => {}
+                    Err(err) => {
+                        log::error!("{}", err.to_string());
+                    }
+                }
+            }
+            Err(err) => {
+                log::warn!("{}", err.to_string());
+            }
         }
     }
     Ok(())
@@ -0,0 +1,375 @@
+use crate::chat::model::{FunctionDefinition, ToolDefinition};
+use crate::cli::schema::{ScriptConfig, SideEffect, Spec};
+use crate::prompt::command::{validate_argument, validate_env_key};
+use custom_logger as log;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// front-matter has to start within the first lines of a script
+const FRONT_MATTER_LINES: usize = 40;
+// and is never searched past this, i.e binaries without newlines
+const FRONT_MATTER_BYTES: u64 = 64 * 1024;
+const FRONT_MATTER_DELIMITER: &str = "# ---";
+
+/// A script described in tools.json or in its front-matter comments
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tool {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "description", default)]
+    pub description: String,
+    /// script path relative to the tools folder
+    #[serde(rename = "script", default)]
+    pub script: String,
+    /// json schema of the named parameters, passed as environment variables
+    #[serde(rename = "parameters", default = "default_parameters")]
+    pub parameters: Value,
+    #[serde(rename = "timeout_secs", default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(rename = "side_effect", default)]
+    pub side_effect: SideEffect,
+    /// environment variables that have to be set for the script to run
+    #[serde(rename = "env", default)]
+    pub env: Vec<String>,
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// described in tools.json, front-matter is written by the script
+    /// itself and its side effect is not trusted for approval
+    #[serde(skip)]
+    pub in_manifest: bool,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(rename = "tools")]
+    tools: Vec<Tool>,
+}
+
+fn default_parameters() -> Value {
+    serde_json::json!({"type": "object", "properties": {}})
+}
+
+/// All tools of the workspace, tools.json entries win over front-matter
+/// with the same name. Loaded once per session and again with /tools
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    pub tools: Vec<Tool>,
+}
+
+impl ToolRegistry {
+    pub fn load(spec: &Spec) -> Result<Self, Box<dyn std::error::Error>> {
+        let folder = Path::new(&spec.working_dir).join(&spec.tools.folder);
+        let folder = folder.canonicalize().unwrap_or(folder);
+        let mut tools = vec![];
+        let manifest = folder.join("tools.json");
+        if manifest.exists() {
+            let data = fs::read_to_string(&manifest)?;
+            let parsed: Manifest = serde_json::from_str(&data)
+                .map_err(|e| format!("[tools] invalid {} : {}", manifest.display(), e))?;
+            tools.extend(parsed.tools.into_iter().map(|t| Tool {
+                in_manifest: true,
+                ..t
+            }));
+        }
+        for entry in WalkDir::new(&folder).into_iter().filter_map(|e| e.ok()) {
+            if !entry.path().is_file() || entry.path() == manifest {
+                continue;
+            }
+            let script = entry
+                .path()
+                .strip_prefix(&folder)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            match front_matter(entry.path(), &script) {
+                Ok(Some(tool)) if !tools.iter().any(|t: &Tool| t.name == tool.name) => {
+                    tools.push(tool)
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("[tools] skipping front-matter of {} : {}", script, e),
+            }
+        }
+        let mut registry = Self { tools: vec![] };
+        for mut tool in tools {
+            let path = folder.join(&tool.script);
+            if tool.script.is_empty() || !path.is_file() {
+                log::warn!(
+                    "[tools] '{}' : script '{}' not found",
+                    tool.name,
+                    tool.script
+                );
+                continue;
+            }
+            if let Err(e) = jsonschema::validator_for(&tool.parameters) {
+                log::warn!(
+                    "[tools] '{}' : invalid parameters schema : {}",
+                    tool.name,
+                    e
+                );
+                continue;
+            }
+            tool.path = path.canonicalize().unwrap_or(path);
+            registry.tools.push(tool);
+        }
+        registry.tools.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(registry)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.name == name)
+    }
+
+    /// the tool for a canonical script path
+    pub fn by_path(&self, path: &Path) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.path == path)
+    }
+
+    /// openai function definitions for tools up to the side effect level
+    pub fn definitions(&self, level: SideEffect) -> Vec<ToolDefinition> {
+        self.tools
+            .iter()
+            .filter(|t| t.side_effect <= level)
+            .map(|t| ToolDefinition {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: t.name.clone(),
+                    description: t.description.clone(),
+                    parameters: t.parameters.clone(),
+                },
+            })
+            .collect()
+    }
+}
+
+impl Tool {
+    /// check the named parameters against the schema, values are typed
+    /// according to the schema before validation (i.e "3" for an integer)
+    pub fn validate(&self, env: &[(String, String)]) -> Result<(), String> {
+        self.validate_with(env, false)
+    }
+
+    // strict forces additionalProperties false, for parameters set by the
+    // model
+    fn validate_with(&self, env: &[(String, String)], strict: bool) -> Result<(), String> {
+        let mut params = Map::new();
+        for (key, value) in env.iter() {
+            let typed = match self.parameters["properties"][key]["type"].as_str() {
+                Some("integer") | Some("number") | Some("boolean") => {
+                    serde_json::from_str(value).unwrap_or(Value::String(value.clone()))
+                }
+                _ => Value::String(value.clone()),
+            };
+            params.insert(key.clone(), typed);
+        }
+        let mut schema = self.parameters.clone();
+        if strict && let Value::Object(schema) = &mut schema {
+            schema.insert("additionalProperties".to_string(), Value::Bool(false));
+        }
+        let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+        let errors: Vec<String> = validator
+            .iter_errors(&Value::Object(params))
+            .map(|e| e.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Err(format!(
+                "invalid parameters for '{}' : {}",
+                self.name,
+                errors.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// model tool call arguments as environment variables, only the
+    /// parameters declared in the schema are accepted
+    pub fn env_from_arguments(&self, arguments: &str) -> Result<Vec<(String, String)>, String> {
+        let arguments: Value = if arguments.trim().is_empty() {
+            Value::Object(Map::new())
+        } else {
+            serde_json::from_str(arguments).map_err(|e| format!("invalid arguments : {}", e))?
+        };
+        let Value::Object(map) = arguments else {
+            return Err("arguments must be a json object".to_string());
+        };
+        let mut env = vec![];
+        for (key, value) in map.into_iter() {
+            let value = match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            if self.parameters["properties"].get(&key).is_none() {
+                return Err(format!("unknown parameter '{}' for '{}'", key, self.name));
+            }
+            validate_env_key(&key)?;
+            validate_argument(&value)?;
+            env.push((key, value));
+        }
+        self.validate_with(&env, true)?;
+        Ok(env)
+    }
+
+    /// required variables set neither in our environment, the script config
+    /// nor the named parameters
+    pub fn missing_env(&self, config: &ScriptConfig, env: &[(String, String)]) -> Vec<String> {
+        self.env
+            .iter()
+            .filter(|key| {
+                std::env::var(key).is_err()
+                    && !config.env.contains_key(*key)
+                    && !env.iter().any(|(k, _)| k == *key)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+// '# key: value' lines between two '# ---' lines near the top of the script,
+// parameters is a json schema on a single line and env a comma separated list
+fn front_matter(path: &Path, script: &str) -> Result<Option<Tool>, Box<dyn std::error::Error>> {
+    let Ok(file) = File::open(path) else {
+        return Ok(None);
+    };
+    // binaries and other non utf-8 files have no front-matter
+    let mut lines = BufReader::new(file.take(FRONT_MATTER_BYTES))
+        .lines()
+        .take(FRONT_MATTER_LINES)
+        .map_while(Result::ok);
+    if !lines.any(|l| l.trim() == FRONT_MATTER_DELIMITER) {
+        return Ok(None);
+    }
+    let mut fields = Map::new();
+    for line in lines {
+        let line = line.trim();
+        if line == FRONT_MATTER_DELIMITER {
+            break;
+        }
+        let Some((key, value)) = line.trim_start_matches('#').split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let value = match key {
+            "parameters" => serde_json::from_str(value)?,
+            "timeout_secs" => Value::from(value.parse::<u64>()?),
+            "env" => Value::from(
+                value
+                    .split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<&str>>(),
+            ),
+            _ => Value::from(value),
+        };
+        fields.insert(key.to_string(), value);
+    }
+    fields.insert("script".to_string(), Value::from(script));
+    Ok(Some(serde_json::from_value(Value::Object(fields))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(parameters: Value) -> Tool {
+        serde_json::from_value(serde_json::json!({
+            "name": "jira",
+            "script": "jira.sh",
+            "parameters": parameters,
+        }))
+        .unwrap()
+    }
+
+    fn issue_tool() -> Tool {
+        tool(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "ISSUE": {"type": "string"},
+                "LIMIT": {"type": "integer"}
+            },
+            "required": ["ISSUE"]
+        }))
+    }
+
+    #[test]
+    fn arguments_become_env() {
+        let env = issue_tool()
+            .env_from_arguments(r#"{"ISSUE": "ABC-1", "LIMIT": 3}"#)
+            .unwrap();
+        assert_eq!(
+            env,
+            vec![
+                ("ISSUE".to_string(), "ABC-1".to_string()),
+                ("LIMIT".to_string(), "3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn arguments_outside_the_schema_are_rejected() {
+        let jira = issue_tool();
+        for key in ["PYTHONPATH", "NODE_OPTIONS", "PERL5OPT", "GIT_SSH_COMMAND"] {
+            let arguments = format!(r#"{{"ISSUE": "ABC-1", "{}": "x"}}"#, key);
+            assert!(jira.env_from_arguments(&arguments).is_err(), "{}", key);
+        }
+        // even when the schema itself allows additional properties
+        let open = tool(serde_json::json!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": true
+        }));
+        assert!(open.env_from_arguments(r#"{"NODE_OPTIONS": "x"}"#).is_err());
+        assert_eq!(open.env_from_arguments("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn arguments_are_validated() {
+        let tool = issue_tool();
+        assert!(tool.env_from_arguments(r#"{"LIMIT": 3}"#).is_err());
+        assert!(
+            tool.env_from_arguments(r#"{"ISSUE": "A", "LIMIT": "x"}"#)
+                .is_err()
+        );
+        assert!(tool.env_from_arguments(r#"{"ISSUE": "$(id)"}"#).is_err());
+        assert!(tool.env_from_arguments(r#"["ISSUE"]"#).is_err());
+    }
+
+    #[test]
+    fn front_matter_fields() {
+        let dir = std::env::temp_dir().join(format!("manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("pods.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\n# ---\n# name: list-pods\n# side_effect: read-only\n\
+             # timeout_secs: 30\n# env: KUBECONFIG, CONTEXT\n\
+             # parameters: {\"type\": \"object\", \"properties\": {\"NS\": {\"type\": \"string\"}}}\n\
+             # ---\nkubectl get pods\n",
+        )
+        .unwrap();
+        let tool = front_matter(&script, "pods.sh").unwrap().unwrap();
+        assert_eq!(tool.name, "list-pods");
+        assert_eq!(tool.side_effect, SideEffect::ReadOnly);
+        assert_eq!(tool.timeout_secs, Some(30));
+        assert_eq!(tool.env, vec!["KUBECONFIG", "CONTEXT"]);
+        assert!(!tool.in_manifest);
+        // no front-matter, and a large file without newlines
+        fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+        assert!(front_matter(&script, "pods.sh").unwrap().is_none());
+        fs::write(&script, vec![0u8; 4 * 1024 * 1024]).unwrap();
+        assert!(front_matter(&script, "pods.sh").unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_allows_user_env() {
+        // variables given with /execute are not limited to the schema
+        let env = vec![
+            ("ISSUE".to_string(), "ABC-1".to_string()),
+            ("DEBUG".to_string(), "1".to_string()),
+        ];
+        assert!(issue_tool().validate(&env).is_ok());
+    }
+}
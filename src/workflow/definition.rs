@@ -0,0 +1,291 @@
+use crate::cli::schema::Spec;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// folder (relative to working_dir) holding the workflow files
+pub const WORKFLOWS_FOLDER: &str = "workflows";
+
+// values accepted in a step's 'when' lists
+const STATUSES: &[&str] = &["ok", "warning", "error", "skipped"];
+
+/// A pipeline of script and prompt steps read from workflows/<name>.yaml
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Workflow {
+    /// defaults to the file name
+    #[serde(rename = "name", default)]
+    pub name: String,
+    #[serde(rename = "description", default)]
+    pub description: String,
+    /// default values, overridden by K=v on /run or --var
+    #[serde(rename = "vars", default)]
+    pub vars: BTreeMap<String, String>,
+    #[serde(rename = "steps")]
+    pub steps: Vec<Step>,
+}
+
+/// Either a script run or a prompt, strings can reference variables and
+/// earlier results with {{name}} and {{steps.<id>.output}}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Step {
+    #[serde(rename = "id")]
+    pub id: String,
+    /// script path relative to working_dir or a tool name
+    #[serde(rename = "run", default)]
+    pub run: Option<String>,
+    #[serde(rename = "args", default)]
+    pub args: Vec<String>,
+    #[serde(rename = "env", default)]
+    pub env: BTreeMap<String, String>,
+    /// prompt template sent to the model
+    #[serde(rename = "prompt", default)]
+    pub prompt: Option<String>,
+    /// file (relative to working_dir) the output is written to
+    #[serde(rename = "save", default)]
+    pub save: Option<String>,
+    /// steps that have to finish first, by default with ok or warning
+    #[serde(rename = "needs", default)]
+    pub needs: Vec<String>,
+    /// statuses of needed steps this step runs on i.e {deploy: [error]}
+    #[serde(rename = "when", default)]
+    pub when: BTreeMap<String, Vec<String>>,
+}
+
+impl Workflow {
+    pub fn load(spec: &Spec, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = workflow_path(spec, name)?;
+        let data = fs::read_to_string(&path)
+            .map_err(|e| format!("[workflow] failed to read {} : {}", path.display(), e))?;
+        let mut workflow: Workflow = serde_yaml::from_str(&data)
+            .map_err(|e| format!("[workflow] invalid {} : {}", path.display(), e))?;
+        if workflow.name.is_empty() {
+            workflow.name = name.to_string();
+        }
+        workflow
+            .validate()
+            .map_err(|e| format!("[workflow] '{}' : {}", name, e))?;
+        Ok(workflow)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Err("no steps".to_string());
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            let valid_id = !step.id.is_empty()
+                && step
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid_id {
+                return Err(format!("invalid step id '{}'", step.id));
+            }
+            if self.steps[..i].iter().any(|s| s.id == step.id) {
+                return Err(format!("duplicate step id '{}'", step.id));
+            }
+            if step.run.is_some() == step.prompt.is_some() {
+                return Err(format!(
+                    "step '{}' needs exactly one of 'run' or 'prompt'",
+                    step.id
+                ));
+            }
+            for need in step.needs.iter() {
+                if !self.steps.iter().any(|s| &s.id == need) {
+                    return Err(format!("step '{}' needs unknown step '{}'", step.id, need));
+                }
+            }
+            for (id, statuses) in step.when.iter() {
+                if !step.needs.contains(id) {
+                    return Err(format!(
+                        "step '{}' has a condition on '{}' which is not in needs",
+                        step.id, id
+                    ));
+                }
+                if let Some(status) = statuses.iter().find(|s| !STATUSES.contains(&s.as_str())) {
+                    return Err(format!(
+                        "step '{}' : unknown status '{}', use {}",
+                        step.id,
+                        status,
+                        STATUSES.join(", ")
+                    ));
+                }
+            }
+        }
+        self.order().map(|_| ())
+    }
+
+    /// step indexes in run order, a step runs after its needs and otherwise
+    /// keeps its place in the file
+    pub fn order(&self) -> Result<Vec<usize>, String> {
+        let mut order: Vec<usize> = vec![];
+        while order.len() < self.steps.len() {
+            let next = self.steps.iter().enumerate().position(|(i, step)| {
+                !order.contains(&i)
+                    && step
+                        .needs
+                        .iter()
+                        .all(|need| order.iter().any(|done| &self.steps[*done].id == need))
+            });
+            match next {
+                Some(i) => order.push(i),
+                None => {
+                    let pending: Vec<&str> = self
+                        .steps
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| !order.contains(i))
+                        .map(|(_, s)| s.id.as_str())
+                        .collect();
+                    return Err(format!(
+                        "dependency cycle between steps {}",
+                        pending.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+/// names of the workflow files, without extension
+pub fn list(spec: &Spec) -> Vec<String> {
+    let folder = Path::new(&spec.working_dir).join(WORKFLOWS_FOLDER);
+    let Ok(entries) = fs::read_dir(folder) else {
+        return vec![];
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("yaml" | "yml")))
+        .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+// workflows/<name>.yaml or .yml, the name is a plain file name
+fn workflow_path(spec: &Spec, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(format!("[workflow] invalid workflow name '{}'", name));
+    }
+    let folder = Path::new(&spec.working_dir).join(WORKFLOWS_FOLDER);
+    ["yaml", "yml"]
+        .iter()
+        .map(|ext| folder.join(format!("{}.{}", name, ext)))
+        .find(|p| p.is_file())
+        .ok_or(format!(
+            "[workflow] no workflow '{}' in '{}', available : {}",
+            name,
+            WORKFLOWS_FOLDER,
+            list(spec).join(", ")
+        ))
+}
+
+/// replace {{name}} placeholders, unknown names are an error
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let key = rest[start + 2..start + end].trim();
+        let value = vars.get(key).ok_or(format!("unknown variable '{}'", key))?;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow(yaml: &str) -> Workflow {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn ids(workflow: &Workflow) -> Vec<&str> {
+        workflow
+            .order()
+            .unwrap()
+            .into_iter()
+            .map(|i| workflow.steps[i].id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn order_follows_needs_then_file_order() {
+        let flow = workflow(
+            "steps:
+  - {id: report, prompt: x, needs: [export, tests]}
+  - {id: export, run: scripts/export.sh}
+  - {id: tests, run: scripts/test.sh, needs: [export]}
+  - {id: lint, run: scripts/lint.sh}",
+        );
+        assert_eq!(ids(&flow), vec!["export", "tests", "report", "lint"]);
+        assert!(flow.validate().is_ok());
+    }
+
+    #[test]
+    fn order_rejects_cycles() {
+        let flow = workflow(
+            "steps:
+  - {id: a, run: x, needs: [c]}
+  - {id: b, run: x, needs: [a]}
+  - {id: c, run: x, needs: [b]}
+  - {id: d, run: x}",
+        );
+        assert_eq!(
+            flow.order(),
+            Err("dependency cycle between steps a, b, c".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_rejects_invalid_steps() {
+        let invalid = [
+            "steps: []",
+            "steps: [{id: 'a b', run: x}]",
+            "steps: [{id: a, run: x}, {id: a, run: y}]",
+            "steps: [{id: a, run: x, prompt: y}]",
+            "steps: [{id: a}]",
+            "steps: [{id: a, run: x, needs: [b]}]",
+            "steps: [{id: a, run: x}, {id: b, run: x, when: {a: [error]}}]",
+            "steps: [{id: a, run: x}, {id: b, run: x, needs: [a], when: {a: [failed]}}]",
+        ];
+        for yaml in invalid {
+            assert!(workflow(yaml).validate().is_err(), "{}", yaml);
+        }
+        let valid =
+            "steps: [{id: a, run: x}, {id: b, run: x, needs: [a], when: {a: [error, skipped]}}]";
+        assert!(workflow(valid).validate().is_ok());
+    }
+
+    #[test]
+    fn render_replaces_placeholders() {
+        let vars: HashMap<String, String> = [
+            ("issue", "ABC-1"),
+            ("steps.export.output", "line 1\nline 2"),
+            ("raw", "{{issue}}"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(
+            render("fix {{issue}}: {{ steps.export.output }}", &vars),
+            Ok("fix ABC-1: line 1\nline 2".to_string())
+        );
+        // values are not rendered again
+        assert_eq!(render("{{raw}}", &vars), Ok("{{issue}}".to_string()));
+        // an unterminated placeholder is kept as text
+        assert_eq!(render("a {{issue", &vars), Ok("a {{issue".to_string()));
+        assert_eq!(
+            render("{{missing}}", &vars),
+            Err("unknown variable 'missing'".to_string())
+        );
+    }
+}
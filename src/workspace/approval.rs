@@ -0,0 +1,236 @@
+use crate::cli::schema::{ApprovalConfig, ApprovalMode, SideEffect};
+use crate::prompt::interact::{Approval, Interact};
+use custom_logger as log;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// A script about to run, as shown to the user
+pub struct ApprovalRequest {
+    /// tool name or script path relative to working_dir, decisions are
+    /// remembered under this key
+    pub key: String,
+    pub command_line: String,
+    pub cwd: String,
+    pub env: Vec<(String, String)>,
+    pub side_effect: Option<SideEffect>,
+    /// the side effect comes from tools.json and not from the script's own
+    /// front-matter
+    pub trusted: bool,
+}
+
+/// Decides whether a script may run, asking the user when the policy
+/// requires it. 'always' answers are remembered for the session
+pub struct ApprovalPolicy {
+    config: ApprovalConfig,
+    approved: Mutex<HashSet<String>>,
+}
+
+impl ApprovalPolicy {
+    pub fn new(config: &ApprovalConfig) -> Self {
+        Self {
+            config: config.clone(),
+            approved: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn check(
+        &self,
+        request: &ApprovalRequest,
+        interact: &dyn Interact,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let allowed = self.config.allow.contains(&request.key);
+        let auto = match self.config.mode {
+            ApprovalMode::Deny => {
+                return Err(Box::from(format!(
+                    "[approval] '{}' denied, the approval mode is deny",
+                    request.key
+                )));
+            }
+            ApprovalMode::Ask => false,
+            ApprovalMode::AllowList => allowed,
+            ApprovalMode::ReadOnly => {
+                allowed || (request.trusted && request.side_effect == Some(SideEffect::ReadOnly))
+            }
+        };
+        if auto || self.approved.lock().unwrap().contains(&request.key) {
+            log::debug!("[approval] '{}' approved by policy", request.key);
+            return Ok(());
+        }
+
+        let mut details = vec![
+            format!("command : {}", request.command_line),
+            format!("cwd     : {}", request.cwd),
+        ];
+        if !request.env.is_empty() {
+            let env: Vec<String> = request
+                .env
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            details.push(format!("params  : {}", env.join(" ")));
+        }
+        if let Some(side_effect) = request.side_effect {
+            details.push(format!("effect  : {}", side_effect));
+        }
+        let title = format!("[approval] run '{}' ?", request.key);
+        match interact.approve(&title, &details).await? {
+            Approval::Yes => Ok(()),
+            Approval::Always => {
+                log::info!("[approval] '{}' approved for this session", request.key);
+                self.approved.lock().unwrap().insert(request.key.clone());
+                Ok(())
+            }
+            Approval::No => Err(Box::from(format!(
+                "[approval] '{}' was not approved",
+                request.key
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // answers every question the same way and counts them
+    struct Reply {
+        answer: Approval,
+        asked: AtomicUsize,
+    }
+
+    impl Reply {
+        fn new(answer: Approval) -> Self {
+            Self {
+                answer,
+                asked: AtomicUsize::new(0),
+            }
+        }
+
+        fn asked(&self) -> usize {
+            self.asked.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl Interact for Reply {
+        async fn choose(
+            &self,
+            _title: &str,
+            _options: &[String],
+        ) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+            Ok(None)
+        }
+
+        async fn approve(
+            &self,
+            _title: &str,
+            _details: &[String],
+        ) -> Result<Approval, Box<dyn std::error::Error>> {
+            self.asked.fetch_add(1, Ordering::SeqCst);
+            Ok(self.answer)
+        }
+    }
+
+    fn policy(mode: ApprovalMode) -> ApprovalPolicy {
+        ApprovalPolicy::new(&ApprovalConfig {
+            mode,
+            allow: vec!["list-pods".to_string()],
+        })
+    }
+
+    fn request(key: &str, side_effect: Option<SideEffect>, trusted: bool) -> ApprovalRequest {
+        ApprovalRequest {
+            key: key.to_string(),
+            command_line: key.to_string(),
+            cwd: "/tmp".to_string(),
+            env: vec![],
+            side_effect,
+            trusted,
+        }
+    }
+
+    #[tokio::test]
+    async fn ask_mode_asks_every_run() {
+        let policy = policy(ApprovalMode::Ask);
+        let reply = Reply::new(Approval::Yes);
+        let list_pods = request("list-pods", Some(SideEffect::ReadOnly), true);
+        assert!(policy.check(&list_pods, &reply).await.is_ok());
+        assert!(policy.check(&list_pods, &reply).await.is_ok());
+        assert_eq!(reply.asked(), 2);
+        let refused = Reply::new(Approval::No);
+        assert!(policy.check(&list_pods, &refused).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn always_is_remembered_per_key() {
+        let policy = policy(ApprovalMode::Ask);
+        let reply = Reply::new(Approval::Always);
+        assert!(
+            policy
+                .check(&request("a", None, false), &reply)
+                .await
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(&request("a", None, false), &reply)
+                .await
+                .is_ok()
+        );
+        assert_eq!(reply.asked(), 1);
+        let refused = Reply::new(Approval::No);
+        assert!(
+            policy
+                .check(&request("b", None, false), &refused)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn deny_mode_never_asks() {
+        let policy = policy(ApprovalMode::Deny);
+        let reply = Reply::new(Approval::Yes);
+        let list_pods = request("list-pods", Some(SideEffect::ReadOnly), true);
+        assert!(policy.check(&list_pods, &reply).await.is_err());
+        assert_eq!(reply.asked(), 0);
+    }
+
+    #[tokio::test]
+    async fn allow_list_mode() {
+        let policy = policy(ApprovalMode::AllowList);
+        let refused = Reply::new(Approval::No);
+        assert!(
+            policy
+                .check(&request("list-pods", None, false), &refused)
+                .await
+                .is_ok()
+        );
+        let read_only = request("get-logs", Some(SideEffect::ReadOnly), true);
+        assert!(policy.check(&read_only, &refused).await.is_err());
+        assert_eq!(refused.asked(), 1);
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_trusts_only_the_manifest() {
+        let policy = policy(ApprovalMode::ReadOnly);
+        let refused = Reply::new(Approval::No);
+        let manifest = request("get-logs", Some(SideEffect::ReadOnly), true);
+        assert!(policy.check(&manifest, &refused).await.is_ok());
+        assert!(
+            policy
+                .check(&request("list-pods", None, false), &refused)
+                .await
+                .is_ok()
+        );
+        assert_eq!(refused.asked(), 0);
+        // a script claiming read-only in its own front-matter is asked
+        let front_matter = request("cleanup", Some(SideEffect::ReadOnly), false);
+        assert!(policy.check(&front_matter, &refused).await.is_err());
+        let mutating = request("restart", Some(SideEffect::Mutating), true);
+        assert!(policy.check(&mutating, &refused).await.is_err());
+        assert_eq!(refused.asked(), 2);
+    }
+}
@@ -0,0 +1,56 @@
+use custom_logger as log;
+use serde_derive::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Debug)]
+pub struct AuditEntry<'a> {
+    pub timestamp: u64,
+    pub action: &'a str,
+    pub path: &'a str,
+    pub allowed: bool,
+    pub reason: &'a str,
+}
+
+/// Append only json lines log at working_dir/audit.log
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(working_dir: &str) -> Self {
+        Self {
+            path: Path::new(working_dir).join("audit.log"),
+        }
+    }
+
+    pub fn record(&self, action: &str, path: &str, allowed: bool, reason: &str) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            action,
+            path,
+            allowed,
+            reason,
+        };
+        // auditing must never break the session, log and carry on
+        let res = serde_json::to_string(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|line| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .and_then(|mut f| writeln!(f, "{}", line))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(err) = res {
+            log::warn!("[audit] failed to write {} : {}", self.path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::cli::schema::{FolderMode, ScriptConfig, Spec};
+use custom_logger as log;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use tokio::process::Command as ProcessCommand;
+
+// landlock abi v1, see linux/landlock.h (libc has the syscall numbers only)
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+const ACCESS_READ: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+// read plus write file, remove dir/file and make char/dir/reg/sock/fifo/block/sym
+const ACCESS_ALL: u64 = (1 << 13) - 1;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+/// Process isolation applied to a script just before exec : resource
+/// limits, a scrubbed environment, the working_dir as cwd, user, mount and
+/// network namespaces and a landlock ruleset limited to the folders
+pub struct Isolation {
+    rlimits: Vec<(u64, u64)>,
+    namespaces: Option<Namespaces>,
+    rules: Vec<(CString, u64)>,
+    env: Vec<(String, String)>,
+    cwd: String,
+}
+
+impl Isolation {
+    pub fn new(spec: &Spec, script: &ScriptConfig) -> Self {
+        let cfg = &spec.execute.isolation;
+        const MB: u64 = 1024 * 1024;
+        let network = script.network.unwrap_or(cfg.network);
+        // a tmpfs on /tmp would hide a workspace that lives under it
+        let root = Path::new(&spec.working_dir);
+        let private_tmp = cfg.private_tmp
+            && !root
+                .canonicalize()
+                .unwrap_or(root.to_path_buf())
+                .starts_with("/tmp");
+        if cfg.private_tmp && !private_tmp {
+            log::warn!("[isolation] working_dir is under /tmp, private_tmp is disabled");
+        }
+        let mut flags = 0;
+        if !network {
+            flags |= libc::CLONE_NEWNET;
+        }
+        if private_tmp {
+            flags |= libc::CLONE_NEWNS;
+        }
+        let namespaces = (flags != 0).then(|| Namespaces::new(flags, private_tmp));
+        let namespaces = match namespaces {
+            Some(ns) if !ns.supported() => {
+                log::warn!(
+                    "[isolation] user namespaces are not permitted, running without network and /tmp isolation"
+                );
+                None
+            }
+            other => other,
+        };
+        let private_tmp = namespaces.as_ref().is_some_and(|ns| ns.private_tmp);
+
+        let mut rules = vec![];
+        if cfg.landlock {
+            for folder in spec.folders.iter() {
+                let path = root.join(&folder.name);
+                let access = match folder.mode {
+                    FolderMode::ReadOnly => ACCESS_READ,
+                    FolderMode::ReadWrite => ACCESS_ALL,
+                };
+                rules.push((path.to_string_lossy().to_string(), access));
+            }
+            rules.extend(cfg.read_paths.iter().map(|p| (p.clone(), ACCESS_READ)));
+            rules.extend(cfg.write_paths.iter().map(|p| (p.clone(), ACCESS_ALL)));
+            if private_tmp {
+                rules.push(("/tmp".to_string(), ACCESS_ALL));
+            }
+        }
+        // paths that do not exist (i.e /lib64) are skipped
+        let rules = rules
+            .into_iter()
+            .filter(|(p, _)| Path::new(p).exists())
+            .filter_map(|(p, access)| CString::new(p).ok().map(|p| (p, access)))
+            .collect();
+
+        let mut env: Vec<(String, String)> = cfg
+            .env
+            .iter()
+            .filter_map(|k| std::env::var(k).ok().map(|v| (k.clone(), v)))
+            .collect();
+        if private_tmp {
+            env.push(("TMPDIR".to_string(), "/tmp".to_string()));
+        }
+
+        Self {
+            // 0 leaves the limit unset
+            rlimits: [
+                (libc::RLIMIT_CPU as u64, cfg.max_cpu_secs),
+                (libc::RLIMIT_AS as u64, cfg.max_memory_mb * MB),
+                (libc::RLIMIT_FSIZE as u64, cfg.max_file_mb * MB),
+                (libc::RLIMIT_NPROC as u64, cfg.max_processes),
+            ]
+            .into_iter()
+            .filter(|(_, limit)| *limit > 0)
+            .collect(),
+            namespaces,
+            rules,
+            env,
+            cwd: spec.working_dir.clone(),
+        }
+    }
+
+    /// replace the environment with the allowed variables, script variables
+    /// are added by the caller afterwards
+    pub fn apply(self, command: &mut ProcessCommand) {
+        command
+            .env_clear()
+            .envs(self.env.clone())
+            .current_dir(&self.cwd);
+        // only async-signal-safe calls from here on, everything is
+        // allocated up front
+        unsafe {
+            command.pre_exec(move || self.enter());
+        }
+    }
+
+    fn enter(&self) -> io::Result<()> {
+        for (resource, limit) in self.rlimits.iter() {
+            let rlimit = libc::rlimit {
+                rlim_cur: *limit as libc::rlim_t,
+                rlim_max: *limit as libc::rlim_t,
+            };
+            check(unsafe { libc::setrlimit(*resource as _, &rlimit) })?;
+        }
+        if let Some(namespaces) = self.namespaces.as_ref() {
+            namespaces.enter()?;
+        }
+        if !self.rules.is_empty() {
+            self.landlock()?;
+        }
+        Ok(())
+    }
+
+    fn landlock(&self) -> io::Result<()> {
+        let attr = RulesetAttr {
+            handled_access_fs: ACCESS_ALL,
+        };
+        let ruleset = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        } as libc::c_int;
+        check(ruleset)?;
+        for (path, access) in self.rules.iter() {
+            let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            check(fd)?;
+            // files only take file rights
+            let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+            check(unsafe { libc::fstat(fd, &mut stat) })?;
+            let allowed = if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+                *access
+            } else {
+                access & (ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE)
+            };
+            let rule = PathBeneathAttr {
+                allowed_access: allowed,
+                parent_fd: fd,
+            };
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset,
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            };
+            unsafe { libc::close(fd) };
+            check(res as libc::c_int)?;
+        }
+        check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+        check(
+            unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) } as libc::c_int,
+        )?;
+        unsafe { libc::close(ruleset) };
+        Ok(())
+    }
+}
+
+/// Network and mount namespaces, inside a user namespace so an unprivileged
+/// process can create them
+#[derive(Clone)]
+struct Namespaces {
+    flags: libc::c_int,
+    uid_map: CString,
+    gid_map: CString,
+    private_tmp: bool,
+}
+
+impl Namespaces {
+    fn new(flags: libc::c_int, private_tmp: bool) -> Self {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Self {
+            flags: flags | libc::CLONE_NEWUSER,
+            uid_map: CString::new(format!("{} {} 1", uid, uid)).unwrap_or_default(),
+            gid_map: CString::new(format!("{} {} 1", gid, gid)).unwrap_or_default(),
+            private_tmp,
+        }
+    }
+
+    // run 'true' inside them, user namespaces can be disabled (sysctl) or
+    // restricted (i.e apparmor on ubuntu 24.04) and every run would fail
+    fn supported(&self) -> bool {
+        let mut probe = std::process::Command::new("true");
+        probe
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        let probe_ns = self.clone();
+        unsafe {
+            probe.pre_exec(move || probe_ns.enter());
+        }
+        probe.status().is_ok_and(|status| status.success())
+    }
+
+    fn enter(&self) -> io::Result<()> {
+        check(unsafe { libc::unshare(self.flags) })?;
+        write_proc(c"/proc/self/setgroups", c"deny")?;
+        write_proc(c"/proc/self/uid_map", &self.uid_map)?;
+        write_proc(c"/proc/self/gid_map", &self.gid_map)?;
+        if self.private_tmp {
+            let none = std::ptr::null();
+            check(unsafe {
+                libc::mount(
+                    none,
+                    c"/".as_ptr(),
+                    none,
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                )
+            })?;
+            check(unsafe {
+                libc::mount(
+                    c"tmpfs".as_ptr(),
+                    c"/tmp".as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV,
+                    std::ptr::null(),
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// landlock abi version of the running kernel, None when unsupported
+pub fn landlock_abi() -> Option<i64> {
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if version > 0 { Some(version) } else { None }
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn write_proc(path: &std::ffi::CStr, content: &std::ffi::CStr) -> io::Result<()> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    check(fd)?;
+    let bytes = content.to_bytes();
+    let written = unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+    unsafe { libc::close(fd) };
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,5 @@
+pub mod approval;
+pub mod audit;
+pub mod isolation;
+pub mod sandbox;
+pub mod startup;
@@ -0,0 +1,256 @@
+use crate::cli::schema::{FolderMode, Spec};
+use crate::workspace::audit::AuditLog;
+use custom_logger as log;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// Allow-list of canonical folder paths built from Spec.folders, every file
+/// access and execution is checked against it
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    folders: Vec<(PathBuf, FolderMode)>,
+    audit: AuditLog,
+}
+
+impl Sandbox {
+    pub fn new(spec: &Spec) -> Self {
+        let root = Path::new(&spec.working_dir);
+        let folders = spec
+            .folders
+            .iter()
+            .map(|f| {
+                let path = root.join(&f.name);
+                (path.canonicalize().unwrap_or(normalize(&path)), f.mode)
+            })
+            .collect();
+        Self {
+            folders,
+            audit: AuditLog::new(&spec.working_dir),
+        }
+    }
+
+    /// canonicalise path and verify it is inside an allowed folder with the
+    /// right mode, violations are audited and returned as errors
+    pub fn check(
+        &self,
+        path: &Path,
+        access: Access,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let resolved = resolve(path);
+        let folder = self
+            .folders
+            .iter()
+            .find(|(folder, _)| resolved.starts_with(folder));
+        let verdict = match folder {
+            None => Err(format!(
+                "'{}' is outside the configured workspace folders",
+                path.display()
+            )),
+            Some((folder, FolderMode::ReadOnly)) if access == Access::Write => {
+                Err(format!("'{}' is read-only", folder.display()))
+            }
+            Some(_) => Ok(()),
+        };
+        let display = resolved.to_string_lossy();
+        match verdict {
+            Ok(()) => {
+                // reads are too frequent to be worth auditing
+                if access != Access::Read {
+                    self.audit.record(&access.to_string(), &display, true, "");
+                }
+                Ok(resolved)
+            }
+            Err(reason) => {
+                self.audit
+                    .record(&access.to_string(), &display, false, &reason);
+                log::warn!("[sandbox] denied {} : {}", access, reason);
+                Err(Box::from(format!(
+                    "[sandbox] {} denied : {}",
+                    access, reason
+                )))
+            }
+        }
+    }
+}
+
+// canonicalise, for paths that do not exist yet the nearest existing
+// ancestor is canonicalised and the remainder normalised
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    let normalized = normalize(path);
+    let mut existing = normalized.as_path();
+    let mut rest: Vec<&std::ffi::OsStr> = vec![];
+    while let Some(parent) = existing.parent() {
+        if let Some(name) = existing.file_name() {
+            rest.push(name);
+        }
+        existing = parent;
+        if let Ok(canonical) = existing.canonicalize() {
+            let mut result = canonical;
+            for name in rest.iter().rev() {
+                result.push(name);
+            }
+            return result;
+        }
+    }
+    normalized
+}
+
+// lexically remove '.' and '..' components
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // fresh directory under the system temp dir, canonicalised so that
+    // comparisons with resolve() hold on systems where /tmp is a symlink
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sandbox-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn sandbox(root: &Path) -> Sandbox {
+        Sandbox {
+            folders: vec![
+                (root.join("docs"), FolderMode::ReadOnly),
+                (root.join("scripts"), FolderMode::ReadWrite),
+            ],
+            audit: AuditLog::new(&root.to_string_lossy()),
+        }
+    }
+
+    #[test]
+    fn normalize_removes_dots() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("/a/b/../../..")), PathBuf::from("/"));
+        assert_eq!(normalize(Path::new("a/../../b")), PathBuf::from("b"));
+    }
+
+    #[test]
+    fn resolve_non_existent_targets() {
+        let root = scratch("resolve");
+        assert_eq!(
+            resolve(&root.join("scripts/new/deeper/file.sh")),
+            root.join("scripts/new/deeper/file.sh")
+        );
+        assert_eq!(
+            resolve(&root.join("scripts/missing/../../outside/x")),
+            root.join("outside/x")
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn check_denies_parent_escapes() {
+        let root = scratch("escape");
+        let sandbox = sandbox(&root);
+        assert!(
+            sandbox
+                .check(&root.join("scripts/run.sh"), Access::Write)
+                .is_ok()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("scripts/../outside/x"), Access::Read)
+                .is_err()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("scripts/new/../../outside/x"), Access::Write)
+                .is_err()
+        );
+        // a sibling sharing the folder name as a prefix is not inside it
+        assert!(
+            sandbox
+                .check(&root.join("scripts-old/x"), Access::Read)
+                .is_err()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn check_follows_symlinks() {
+        let root = scratch("symlink");
+        let sandbox = sandbox(&root);
+        fs::write(root.join("outside/secret"), "x").unwrap();
+        std::os::unix::fs::symlink(root.join("outside"), root.join("scripts/link")).unwrap();
+        std::os::unix::fs::symlink(root.join("outside/secret"), root.join("docs/secret")).unwrap();
+        assert!(
+            sandbox
+                .check(&root.join("scripts/link/secret"), Access::Read)
+                .is_err()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("scripts/link/new"), Access::Write)
+                .is_err()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("docs/secret"), Access::Read)
+                .is_err()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn check_enforces_read_only() {
+        let root = scratch("readonly");
+        let sandbox = sandbox(&root);
+        assert!(
+            sandbox
+                .check(&root.join("docs/notes.md"), Access::Read)
+                .is_ok()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("docs/notes.md"), Access::Write)
+                .is_err()
+        );
+        assert!(
+            sandbox
+                .check(&root.join("docs/notes.md"), Access::Execute)
+                .is_ok()
+        );
+        let audit = fs::read_to_string(root.join("audit.log")).unwrap();
+        assert!(audit.contains("read-only"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use crate::cli::schema::{Spec, StagingConfig, StartupPolicy};
+use chrono::Local;
+use custom_logger as log;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+const ARCHIVE_FOLDER: &str = "archive";
+
+// staging entries that survive archive and purge, the response history is
+// compared across sessions
+const KEPT: &[&str] = &[ARCHIVE_FOLDER, "history", LATEST];
+
+const LATEST: &str = "latest.md";
+
+/// create the workspace and its folders when missing, then apply the
+/// staging startup policy and prune old files
+pub fn prepare(spec: &Spec) -> Result<(), Box<dyn std::error::Error>> {
+    let root = Path::new(&spec.working_dir);
+    let staging = root.join("staging");
+    let folders = spec.folder_names().into_iter().map(|f| root.join(f));
+    for folder in folders.chain([staging.clone()]) {
+        if !folder.exists() {
+            fs::create_dir_all(&folder)
+                .map_err(|e| format!("[startup] failed to create {} : {}", folder.display(), e))?;
+            log::info!("[startup] created {}", folder.display());
+        }
+    }
+
+    let previous = previous_outputs(&staging)?;
+    if !previous.is_empty() {
+        match spec.staging.on_start {
+            StartupPolicy::Keep => {}
+            StartupPolicy::Archive => {
+                let archive = archive(&staging, &previous)?;
+                remove(&previous)?;
+                log::info!(
+                    "[startup] archived {} staging entries to {}",
+                    previous.len(),
+                    spec.relative_path(&archive)
+                );
+            }
+            StartupPolicy::Purge => {
+                remove(&previous)?;
+                log::info!("[startup] purged {} staging entries", previous.len());
+            }
+        }
+    }
+    rotate_archives(&staging.join(ARCHIVE_FOLDER), spec.staging.max_archives)?;
+    prune(&staging, &spec.staging)
+}
+
+// top level entries of staging except the kept ones
+fn previous_outputs(staging: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(staging)?
+        .filter_map(|e| e.ok())
+        .filter(|e| !KEPT.iter().any(|kept| e.file_name() == *kept))
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+fn archive(staging: &Path, entries: &[PathBuf]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let folder = staging.join(ARCHIVE_FOLDER);
+    fs::create_dir_all(&folder)?;
+    let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut path = folder.join(format!("{}.tar", stamp));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = folder.join(format!("{}-{}.tar", stamp, n));
+    }
+    let mut builder = tar::Builder::new(File::create(&path)?);
+    // i.e latest.md is stored as the link it is
+    builder.follow_symlinks(false);
+    for entry in entries.iter() {
+        let name = entry.strip_prefix(staging)?;
+        if entry.symlink_metadata()?.is_dir() {
+            builder.append_dir_all(name, entry)?;
+        } else {
+            builder.append_path_with_name(entry, name)?;
+        }
+    }
+    builder
+        .into_inner()
+        .map_err(|e| format!("[startup] failed to write {} : {}", path.display(), e))?;
+    Ok(path)
+}
+
+// keep the newest max archives, every launch (i.e a workflow run from cron)
+// adds one
+fn rotate_archives(folder: &Path, max: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if max == 0 || !folder.exists() {
+        return Ok(());
+    }
+    let mut archives: Vec<(SystemTime, PathBuf)> = fs::read_dir(folder)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "tar"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    // newest first
+    archives.sort_by(|a, b| b.cmp(a));
+    for (_, path) in archives.iter().skip(max) {
+        fs::remove_file(path)
+            .map_err(|e| format!("[startup] failed to remove {} : {}", path.display(), e))?;
+        log::debug!("[startup] removed old archive {}", path.display());
+    }
+    Ok(())
+}
+
+fn remove(entries: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in entries.iter() {
+        let result = if entry.symlink_metadata()?.is_dir() {
+            fs::remove_dir_all(entry)
+        } else {
+            fs::remove_file(entry)
+        };
+        result.map_err(|e| format!("[startup] failed to remove {} : {}", entry.display(), e))?;
+    }
+    Ok(())
+}
+
+// each folder under staging keeps its newest max_files files, younger
+// than max_age_days, history included
+fn prune(staging: &Path, config: &StagingConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if config.max_age_days.is_none() && config.max_files.is_none() {
+        return Ok(());
+    }
+    let cutoff = config
+        .max_age_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 3600)));
+    let latest = staging.join(LATEST);
+    let mut removed = 0;
+    for dir in WalkDir::new(staging)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+    {
+        let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir.path())?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.path().symlink_metadata().ok()?;
+                if meta.is_dir() || e.path() == latest {
+                    return None;
+                }
+                Some((meta.modified().ok()?, e.path()))
+            })
+            .collect();
+        // newest first
+        files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        for (i, (modified, path)) in files.iter().enumerate() {
+            let too_many = config.max_files.is_some_and(|max| i >= max);
+            let too_old = cutoff.is_some_and(|cutoff| *modified < cutoff);
+            if too_many || too_old {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+    }
+    if removed > 0 {
+        log::info!("[startup] pruned {} old staging files", removed);
+    }
+    // the response it pointed to may have been pruned
+    if latest.symlink_metadata().is_ok() && !latest.exists() {
+        fs::remove_file(&latest)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_keeps_the_newest_archives() {
+        let folder = std::env::temp_dir().join(format!("startup-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let now = SystemTime::now();
+        for i in 0..12u64 {
+            let file = File::create(folder.join(format!("{:02}.tar", i))).unwrap();
+            file.set_modified(now - Duration::from_secs(100 - i))
+                .unwrap();
+        }
+        fs::write(folder.join("notes.md"), "x").unwrap();
+        rotate_archives(&folder, 10).unwrap();
+        let mut left: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left.len(), 11);
+        assert_eq!(left[0], "02.tar");
+        assert!(left.contains(&"notes.md".to_string()));
+        // 0 keeps them all
+        rotate_archives(&folder, 0).unwrap();
+        assert_eq!(fs::read_dir(&folder).unwrap().count(), 11);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}